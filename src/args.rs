@@ -1,10 +1,70 @@
-use clap::Parser;
+use crate::config::Os;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "Easy Config")]
 #[command(version = "1.0")]
 #[command(about = "easy config new pc", long_about = None)]
 pub struct Args {
-    #[arg(short, long, default_value = "./")]
+    #[arg(short, long, default_value = "./", global = true)]
     pub directory: String,
+    /// Use the config section of this OS instead of the running one
+    #[arg(long, global = true)]
+    pub os: Option<Os>,
+    /// Only use the tasks of this profile instead of the one matching the hostname
+    #[arg(short, long, global = true)]
+    pub profile: Option<String>,
+    /// Only show tasks with one of these tags
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub tags: Vec<String>,
+    /// Hide tasks with one of these tags
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub skip_tags: Vec<String>,
+    /// Show what the tasks would do without changing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// A JSON array of every step, once the run is over
+    Json,
+    /// A JSON object per line as each task finishes
+    Jsonl,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Validate the config without executing anything
+    Check {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run tasks without the interface, printing their output
+    Run {
+        /// Names of the tasks to run, in order
+        #[arg(required_unless_present = "all", conflicts_with_all = ["all", "force"])]
+        tasks: Vec<String>,
+        /// Run every task passing the tag filter, skipping those unchanged since their last run
+        #[arg(long)]
+        all: bool,
+        /// With `--all`, also run the tasks unchanged since their last run
+        #[arg(long, requires = "all")]
+        force: bool,
+        /// Print one record per step instead of the output of the steps
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// List the tasks passing the tag filter with the status of their last run
+    List,
+    /// Report how the machine drifted from the config, without executing anything
+    Status {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
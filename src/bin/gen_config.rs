@@ -9,7 +9,7 @@ fn main() {
     }
     let path = PathBuf::from(testdir).join("tasks.yaml");
     let cfg = Config {
-        linux: Some(LinuxConfig {
+        linux: Some(OsConfig {
             tasks: vec![
                 Task {
                     name: "test".to_string(),
@@ -21,6 +21,8 @@ fn main() {
                     }]),
                     commands: None,
                     slink: None,
+                    depends: None,
+                    ..Default::default()
                 },
                 Task {
                     name: "test2".to_string(),
@@ -30,11 +32,14 @@ fn main() {
                         install: Some(vec!["test".to_string()]),
                         remove: Some(vec!["test".to_string()]),
                     }]),
-                    commands: Some(vec!["test".to_string()]),
-                    slink: Some(vec![("test".to_string(), "test".to_string())]),
+                    commands: Some(vec!["test".into()]),
+                    slink: Some(vec![("test", "test").into()]),
+                    depends: Some(vec!["test".to_string()]),
+                    ..Default::default()
                 },
             ],
         }),
+        ..Default::default()
     };
     let mut xf = xcfg::File::default().path(path.to_str().unwrap());
     xf.inner = cfg;
@@ -1,13 +1,22 @@
+mod check;
 mod dis;
+mod expand;
+mod facts;
+mod os;
 mod os_rel;
+pub mod vars;
+pub mod when;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+pub use check::{check, Issue, IssueKind, Severity};
 pub use dis::Distri;
+pub use facts::Facts;
+pub use os::Os;
 pub use os_rel::get_release;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+pub use vars::Vars;
 
 use serde::{Deserialize, Serialize};
-use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use xcfg::File as XFile;
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -17,62 +26,636 @@ pub struct Package {
     pub remove: Option<Vec<String>>,
 }
 
+/// A shell command, either a string or a table with a condition
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "CmdDef")]
+pub struct Cmd {
+    pub run: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CmdDef {
+    Plain(String),
+    Full { run: String, when: Option<String> },
+}
+
+impl From<CmdDef> for Cmd {
+    fn from(def: CmdDef) -> Self {
+        match def {
+            CmdDef::Plain(run) => Self { run, when: None },
+            CmdDef::Full { run, when } => Self { run, when },
+        }
+    }
+}
+
+impl From<&str> for Cmd {
+    fn from(run: &str) -> Self {
+        Self {
+            run: run.to_string(),
+            when: None,
+        }
+    }
+}
+
+/// How a [`Symlink`] puts its source in place
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    /// Copy the source, skipping files whose content did not change
+    Copy,
+    Hardlink,
+}
+
+impl LinkMode {
+    fn is_symlink(&self) -> bool {
+        *self == LinkMode::Symlink
+    }
+}
+
+/// A symlink, either a `[src, dst]` pair or a table with a condition and mode.
+/// A glob `src` such as `bin/*` links each match into the `dst` directory, a
+/// glob matching nothing is an error.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "PairDef")]
+pub struct Symlink {
+    pub src: String,
+    pub dst: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    #[serde(skip_serializing_if = "LinkMode::is_symlink")]
+    pub mode: LinkMode,
+}
+
+/// A `[src, dst]` pair or a table with a condition
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PairDef {
+    Pair(String, String),
+    Full {
+        src: String,
+        dst: String,
+        when: Option<String>,
+        #[serde(default)]
+        mode: LinkMode,
+    },
+}
+
+impl From<PairDef> for Symlink {
+    fn from(def: PairDef) -> Self {
+        match def {
+            PairDef::Pair(src, dst) => Self {
+                src,
+                dst,
+                when: None,
+                mode: LinkMode::Symlink,
+            },
+            PairDef::Full {
+                src,
+                dst,
+                when,
+                mode,
+            } => Self {
+                src,
+                dst,
+                when,
+                mode,
+            },
+        }
+    }
+}
+
+impl From<(&str, &str)> for Symlink {
+    fn from((src, dst): (&str, &str)) -> Self {
+        Self {
+            src: src.to_string(),
+            dst: dst.to_string(),
+            when: None,
+            mode: LinkMode::Symlink,
+        }
+    }
+}
+
+/// A file rendered with the variables of its task, written like a symlink
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "PairDef")]
+pub struct Template {
+    pub src: String,
+    pub dst: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// The rendered source, filled in by [`init`]
+    #[serde(skip)]
+    pub content: String,
+    /// Why the source could not be read or rendered, reported by [`check`]
+    #[serde(skip)]
+    pub error: Option<String>,
+}
+
+impl From<PairDef> for Template {
+    fn from(def: PairDef) -> Self {
+        let link = Symlink::from(def);
+        Self {
+            src: link.src,
+            dst: link.dst,
+            when: link.when,
+            content: String::new(),
+            error: None,
+        }
+    }
+}
+
+/// A directory tree mirrored into `dst` by symlinking its files, like GNU stow
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Stow {
+    pub src: String,
+    pub dst: String,
+    /// Glob patterns of the entries to leave out, matched against their path
+    /// relative to `src` and against their name
+    pub ignore: Option<Vec<String>>,
+    /// Link a whole directory when it is missing in `dst` instead of its files
+    #[serde(default)]
+    pub fold: bool,
+    pub when: Option<String>,
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Task {
     pub name: String,
     pub path: Option<String>,
     pub pkg: Option<Vec<Package>>,
-    pub commands: Option<Vec<String>>,
-    pub slink: Option<Vec<(String, String)>>,
+    pub commands: Option<Vec<Cmd>>,
+    /// Commands run when the task is undone, before its links are removed
+    pub uninstall_commands: Option<Vec<Cmd>>,
+    pub slink: Option<Vec<Symlink>>,
+    pub templates: Option<Vec<Template>>,
+    pub stow: Option<Vec<Stow>>,
+    pub depends: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    /// Only keep the task on machines matching this condition, see [`when`]
+    pub when: Option<String>,
+    /// Variables of this task, overriding the config ones, see [`vars`]
+    pub vars: Option<Vars>,
+    /// The config file defining this task, relative to the config directory
+    #[serde(skip)]
+    pub file: PathBuf,
+}
+
+impl Task {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().flatten().any(|t| t == tag)
+    }
+    /// Overrides the fields of this task that are set in `other`.
+    fn merge(&mut self, other: Task) {
+        self.path = other.path.or(self.path.take());
+        self.pkg = other.pkg.or(self.pkg.take());
+        self.commands = other.commands.or(self.commands.take());
+        self.uninstall_commands = other.uninstall_commands.or(self.uninstall_commands.take());
+        self.slink = other.slink.or(self.slink.take());
+        self.templates = other.templates.or(self.templates.take());
+        self.stow = other.stow.or(self.stow.take());
+        self.depends = other.depends.or(self.depends.take());
+        self.tags = other.tags.or(self.tags.take());
+        self.when = other.when.or(self.when.take());
+        self.vars = other.vars.or(self.vars.take());
+    }
+    /// Interpolates the variables in every field but the name, and expands
+    /// the environment variables in the paths.
+    fn render(&mut self, global: &Vars, facts: &Facts) -> Result<()> {
+        let vars = self.vars.clone();
+        let ctx = vars::Context {
+            facts,
+            global,
+            local: vars.as_ref(),
+        };
+        let (file, name) = (self.file.clone(), self.name.clone());
+        let render = |s: &mut String| -> Result<()> {
+            *s = ctx
+                .render(s)
+                .map_err(|e| anyhow!("{}: task {}: {}", file.display(), name, e))?;
+            Ok(())
+        };
+        let path = |s: &mut String| -> Result<()> {
+            render(s)?;
+            *s = expand::expand(s, &facts.home)
+                .map_err(|e| anyhow!("{}: task {}: {}", file.display(), name, e))?;
+            Ok(())
+        };
+        self.path.iter_mut().try_for_each(path)?;
+        for pkg in self.pkg.iter_mut().flatten() {
+            pkg.install.iter_mut().flatten().try_for_each(render)?;
+            pkg.remove.iter_mut().flatten().try_for_each(render)?;
+        }
+        let commands = self.commands.iter_mut().flatten();
+        for cmd in commands.chain(self.uninstall_commands.iter_mut().flatten()) {
+            render(&mut cmd.run)?;
+        }
+        for link in self.slink.iter_mut().flatten() {
+            path(&mut link.src)?;
+            path(&mut link.dst)?;
+        }
+        for tpl in self.templates.iter_mut().flatten() {
+            path(&mut tpl.src)?;
+            path(&mut tpl.dst)?;
+        }
+        for stow in self.stow.iter_mut().flatten() {
+            path(&mut stow.src)?;
+            path(&mut stow.dst)?;
+        }
+        Ok(())
+    }
+    /// Reads and renders the sources of the templates, once their paths are
+    /// resolved. A template failing to render keeps the error for [`check`]
+    /// and the run to report.
+    fn render_templates(&mut self, global: &Vars, facts: &Facts) {
+        let ctx = vars::Context {
+            facts,
+            global,
+            local: self.vars.as_ref(),
+        };
+        for tpl in self.templates.iter_mut().flatten() {
+            match std::fs::read_to_string(&tpl.src)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| ctx.render(&text))
+            {
+                Ok(content) => tpl.content = content,
+                Err(e) => tpl.error = Some(format!("template {}: {}", tpl.src, e)),
+            }
+        }
+    }
 }
+
 #[derive(Debug, Default, Deserialize, Serialize)]
-pub struct LinuxConfig {
+pub struct OsConfig {
     pub tasks: Vec<Task>,
 }
 
+/// A named subset of the tasks
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    pub name: String,
+    /// Hostname patterns selecting the profile when none is given, e.g. `work-*`
+    pub hosts: Option<Vec<String>>,
+    /// Tasks of the profile, their dependencies are selected too
+    pub tasks: Option<Vec<String>>,
+    /// Select the tasks with one of these tags too
+    pub tags: Option<Vec<String>>,
+    /// The config file defining this profile, relative to the config directory
+    #[serde(skip)]
+    pub file: PathBuf,
+}
+
+impl Profile {
+    pub fn matches(&self, hostname: &str) -> bool {
+        self.hosts
+            .iter()
+            .flatten()
+            .any(|host| glob::Pattern::new(host).is_ok_and(|pattern| pattern.matches(hostname)))
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
-    pub linux: Option<LinuxConfig>,
+    /// Other config files to merge, globs resolved against this file's directory
+    pub include: Option<Vec<String>>,
+    /// Variables of every task, those of the including file take precedence
+    pub vars: Option<Vars>,
+    /// Tasks shared by every operating system, overridden by name in its section
+    pub common: Option<OsConfig>,
+    pub linux: Option<OsConfig>,
+    pub macos: Option<OsConfig>,
+    pub freebsd: Option<OsConfig>,
+    pub profiles: Option<Vec<Profile>>,
+}
+
+impl Config {
+    fn take_os(&mut self, os: Os) -> Option<OsConfig> {
+        match os {
+            Os::Linux => self.linux.take(),
+            Os::Macos => self.macos.take(),
+            Os::Freebsd => self.freebsd.take(),
+        }
+    }
 }
 
-pub type Cfg = LinuxConfig;
+#[derive(Debug, Default)]
+pub struct Cfg {
+    pub tasks: Vec<Task>,
+    pub vars: Vars,
+    pub profiles: Vec<Profile>,
+    /// The profile the tasks were selected by
+    pub profile: Option<String>,
+}
 
-pub fn init(dir: String) -> Result<(Cfg, PathBuf)> {
-    let home = dirs::home_dir().ok_or(IoError::new(
-        IoErrorKind::Other,
-        "Could not find home directory",
-    ))?;
-    let cwd = match dir.strip_prefix("~/") {
-        Some(p) => home.join(p),
-        None => PathBuf::from(dir),
-    };
-    let cwd = std::fs::canonicalize(cwd)?;
+impl Cfg {
+    /// Keeps the tasks of the profile `name`, or when `None` of the first
+    /// profile matching the hostname. Without a profile all tasks are kept.
+    pub fn select_profile(&mut self, name: Option<&str>, facts: &Facts) -> Result<()> {
+        let profile = match name {
+            Some(name) => Some(
+                self.profiles
+                    .iter()
+                    .find(|p| p.name == name)
+                    .ok_or(anyhow!("unknown profile {}", name))?,
+            ),
+            None => self.profiles.iter().find(|p| p.matches(&facts.hostname)),
+        };
+        let Some(profile) = profile else {
+            return Ok(());
+        };
+        let mut names: Vec<String> = profile.tasks.clone().unwrap_or_default();
+        for task in &self.tasks {
+            if profile.tags.iter().flatten().any(|tag| task.has_tag(tag)) {
+                names.push(task.name.clone());
+            }
+        }
+        let mut i = 0;
+        while i < names.len() {
+            let name = names[i].clone();
+            for task in self.tasks.iter().filter(|t| t.name == name) {
+                for dep in task.depends.iter().flatten() {
+                    if !names.contains(dep) {
+                        names.push(dep.clone());
+                    }
+                }
+            }
+            i += 1;
+        }
+        self.profile = Some(profile.name.clone());
+        self.tasks.retain(|t| names.contains(&t.name));
+        Ok(())
+    }
+}
+
+impl Cfg {
+    /// Every tag used by a task, sorted
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .tasks
+            .iter()
+            .flat_map(|t| t.tags.clone().unwrap_or_default())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+    /// Orders the tasks at `indexes` so that each one runs after those it
+    /// depends on, pulling in the dependencies missing from `indexes` and
+    /// keeping the given order otherwise. A dependency cycle is broken where
+    /// it is first met.
+    pub fn order(&self, indexes: &[usize]) -> Vec<usize> {
+        fn visit(cfg: &Cfg, index: usize, seen: &mut Vec<usize>, order: &mut Vec<usize>) {
+            if seen.contains(&index) {
+                return;
+            }
+            seen.push(index);
+            for dep in cfg.tasks[index].depends.iter().flatten() {
+                if let Some(dep) = cfg.tasks.iter().position(|t| t.name == *dep) {
+                    visit(cfg, dep, seen, order);
+                }
+            }
+            order.push(index);
+        }
+        let (mut seen, mut order) = (Vec::new(), Vec::new());
+        for &index in indexes {
+            visit(self, index, &mut seen, &mut order);
+        }
+        order
+    }
+    /// Whether the task at `index` depends on one of the tasks named in
+    /// `names`, directly.
+    pub fn depends_on(&self, index: usize, names: &[String]) -> bool {
+        self.tasks[index]
+            .depends
+            .iter()
+            .flatten()
+            .any(|dep| names.contains(dep))
+    }
+}
+
+/// Selects the tasks having one of `tags`, or all when it is empty,
+/// except those having one of `skip`
+#[derive(Debug, Default, Clone)]
+pub struct TagFilter {
+    pub tags: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        (self.tags.is_empty() || self.tags.iter().any(|t| task.has_tag(t)))
+            && !self.skip.iter().any(|t| task.has_tag(t))
+    }
+}
 
-    let cfg_dir = cwd.join("tasks.toml");
-    let mut xf = XFile::<Config>::default().path(cfg_dir.to_str().unwrap());
-    xf.load().unwrap();
+/// Everything collected from a config file and the files it includes
+#[derive(Default)]
+struct Fragments {
+    files: Vec<PathBuf>,
+    vars: Vars,
+    common: Vec<Task>,
+    specific: Vec<Task>,
+    profiles: Vec<Profile>,
+}
+
+pub fn init(dir: String, facts: &Facts) -> Result<(Cfg, PathBuf)> {
+    let cwd = std::fs::canonicalize(expand::expand(&dir, &facts.home)?)?;
+    let facts = &Facts {
+        dir: cwd.clone(),
+        ..facts.clone()
+    };
 
-    let mut tasks = {
-        #[cfg(target_os = "linux")]
-        {
-            xf.inner.linux.unwrap_or_default()
+    let mut fragments = Fragments::default();
+    load(&cwd, &cwd.join("tasks.toml"), facts.os, &mut fragments)?;
+    let mut tasks = fragments.common;
+    let common = tasks.len();
+    for task in fragments.specific {
+        match tasks[..common].iter_mut().find(|t| t.name == task.name) {
+            Some(common) => common.merge(task),
+            None => tasks.push(task),
         }
+    }
+    let mut selected = Vec::new();
+    for mut task in tasks {
+        if !when(&task, &task.when, facts)? {
+            continue;
+        }
+        let commands = task.commands.take();
+        task.commands = select(&task, commands, |c| &c.when, facts)?;
+        let commands = task.uninstall_commands.take();
+        task.uninstall_commands = select(&task, commands, |c| &c.when, facts)?;
+        let slink = task.slink.take();
+        task.slink = select(&task, slink, |s| &s.when, facts)?;
+        let templates = task.templates.take();
+        task.templates = select(&task, templates, |t| &t.when, facts)?;
+        let stow = task.stow.take();
+        task.stow = select(&task, stow, |s| &s.when, facts)?;
+        task.render(&fragments.vars, facts)?;
+        selected.push(task);
+    }
+    let mut cfg = Cfg {
+        tasks: selected,
+        vars: fragments.vars,
+        profiles: fragments.profiles,
+        profile: None,
     };
-    for task in &mut tasks.tasks {
+    for task in &mut cfg.tasks {
         let src_parent = cwd.join(match &task.path {
             Some(path) => path,
             None => "",
         });
-        if let Some(slinks) = &mut task.slink {
-            for (src, dst) in slinks {
-                *src = src_parent.join(src.clone()).to_string_lossy().to_string();
-                if let Some(rest) = dst.strip_prefix("~/") {
-                    *dst = home.join(rest).to_string_lossy().to_string();
-                }
+        if let Some(links) = task.slink.take() {
+            task.slink = Some(expand_links(task, &src_parent, links)?);
+        }
+        let links = task.slink.iter_mut().flatten().map(|l| &mut l.src);
+        let templates = task.templates.iter_mut().flatten().map(|t| &mut t.src);
+        let stow = task.stow.iter_mut().flatten().map(|s| &mut s.src);
+        for src in links.chain(templates).chain(stow) {
+            *src = src_parent.join(src.clone()).to_string_lossy().to_string();
+        }
+        task.render_templates(&cfg.vars, facts);
+    }
+    Ok((cfg, cwd))
+}
+
+/// Replaces the symlinks with a glob source by one symlink per match, into
+/// the destination directory.
+fn expand_links(task: &Task, src_parent: &Path, links: Vec<Symlink>) -> Result<Vec<Symlink>> {
+    let mut expanded = Vec::new();
+    for link in links {
+        if !link.src.contains(['*', '?', '[']) {
+            expanded.push(link);
+            continue;
+        }
+        let pattern =
+            Path::new(&glob::Pattern::escape(&src_parent.to_string_lossy())).join(&link.src);
+        let error = |e: &dyn std::fmt::Display| {
+            anyhow!(
+                "{}: task {}: slink {}: {}",
+                task.file.display(),
+                task.name,
+                link.src,
+                e
+            )
+        };
+        let start = expanded.len();
+        for entry in glob::glob(&pattern.to_string_lossy()).map_err(|e| error(&e))? {
+            let entry = entry.map_err(|e| error(&e))?;
+            let Some(name) = entry.file_name() else {
+                continue;
+            };
+            // an absolute pattern, `~/scripts/*` once expanded, is kept as is
+            let src = entry.strip_prefix(src_parent).unwrap_or(&entry);
+            expanded.push(Symlink {
+                src: src.to_string_lossy().to_string(),
+                dst: Path::new(&link.dst)
+                    .join(name)
+                    .to_string_lossy()
+                    .to_string(),
+                ..link.clone()
+            });
+        }
+        if expanded.len() == start {
+            return Err(error(&"the pattern matches nothing"));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Evaluates the condition of a task or of one of its steps.
+fn when(task: &Task, cond: &Option<String>, facts: &Facts) -> Result<bool> {
+    match cond {
+        None => Ok(true),
+        Some(cond) => when::eval(cond, facts).map_err(|e| {
+            anyhow!(
+                "{}: task {}: when {:?}: {}",
+                task.file.display(),
+                task.name,
+                cond,
+                e
+            )
+        }),
+    }
+}
+
+/// Keeps the steps whose condition holds.
+fn select<T>(
+    task: &Task,
+    steps: Option<Vec<T>>,
+    cond: fn(&T) -> &Option<String>,
+    facts: &Facts,
+) -> Result<Option<Vec<T>>> {
+    let Some(steps) = steps else {
+        return Ok(None);
+    };
+    let mut selected = Vec::new();
+    for step in steps {
+        if when(task, cond(&step), facts)? {
+            selected.push(step);
+        }
+    }
+    Ok(Some(selected))
+}
+
+/// Loads the config file at `path` and, after it, every file it includes,
+/// collecting the common tasks and the ones of the `os` section separately.
+fn load(cwd: &Path, path: &Path, os: Os, fragments: &mut Fragments) -> Result<()> {
+    let path = std::fs::canonicalize(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    if fragments.files.contains(&path) {
+        return Ok(());
+    }
+    fragments.files.push(path.clone());
+    let mut xf = XFile::<Config>::default().path(path.to_str().unwrap());
+    xf.load()
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+    let file = path.strip_prefix(cwd).unwrap_or(&path);
+    for (section, tasks) in [
+        (xf.inner.common.take(), &mut fragments.common),
+        (xf.inner.take_os(os), &mut fragments.specific),
+    ] {
+        let mut section = section.unwrap_or_default();
+        for task in &mut section.tasks {
+            task.file = file.to_path_buf();
+        }
+        tasks.extend(section.tasks);
+    }
+    for (name, value) in xf.inner.vars.take().unwrap_or_default() {
+        fragments.vars.entry(name).or_insert(value);
+    }
+    for mut profile in xf.inner.profiles.take().unwrap_or_default() {
+        profile.file = file.to_path_buf();
+        fragments.profiles.push(profile);
+    }
+
+    let parent = path.parent().unwrap_or(cwd);
+    for include in xf.inner.include.iter().flatten() {
+        let pattern = Path::new(&glob::Pattern::escape(&parent.to_string_lossy())).join(include);
+        let paths = glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| anyhow!("{}: include {}: {}", path.display(), include, e))?;
+        let mut matched = false;
+        for entry in paths {
+            let entry =
+                entry.map_err(|e| anyhow!("{}: include {}: {}", path.display(), include, e))?;
+            if entry.is_file() {
+                load(cwd, &entry, os, fragments)?;
+                matched = true;
             }
         }
+        if !matched && !include.contains(['*', '?', '[']) {
+            return Err(anyhow!(
+                "{}: include {}: no such file",
+                path.display(),
+                include
+            ));
+        }
     }
-    Ok((tasks, cwd))
+    Ok(())
 }
 
 #[cfg(test)]
@@ -86,10 +669,10 @@ mod tests {
 
     #[test]
     fn test_init() {
-        let testdir = "unit_config_test";
+        let testdir = "unit_config_init_test";
         let hook = std::panic::take_hook();
         panic::set_hook(Box::new(move |info| {
-            remove_dir_all(testdir).unwrap();
+            let _ = remove_dir_all(testdir);
             hook(info);
         }));
         fs::create_dir(testdir).unwrap();
@@ -113,54 +696,505 @@ mod tests {
         "#,
         )
         .unwrap();
-        // let cfg = init(PathBuf::from(testdir));
-        // assert_eq!(cfg.tasks.len(), 2);
-        // assert_eq!(cfg.tasks[0].name, "test");
-        // assert_eq!(cfg.tasks[0].pkg.as_ref().unwrap()[0].distri, Distri::Arch);
-        // assert_eq!(
-        //     cfg.tasks[0].pkg.as_ref().unwrap()[0]
-        //         .install
-        //         .as_ref()
-        //         .unwrap()[0],
-        //     "test"
-        // );
-        // assert_eq!(
-        //     cfg.tasks[0].pkg.as_ref().unwrap()[0]
-        //         .remove
-        //         .as_ref()
-        //         .unwrap()[0],
-        //     "test"
-        // );
-        // assert_eq!(cfg.tasks[1].name, "test2");
-        // assert_eq!(cfg.tasks[1].pkg.as_ref().unwrap()[0].distri, Distri::Arch);
-        // assert_eq!(
-        //     cfg.tasks[1].pkg.as_ref().unwrap()[0]
-        //         .install
-        //         .as_ref()
-        //         .unwrap()[0],
-        //     "test"
-        // );
-        // assert_eq!(
-        //     cfg.tasks[1].pkg.as_ref().unwrap()[0]
-        //         .remove
-        //         .as_ref()
-        //         .unwrap()[0],
-        //     "test"
-        // );
-        // remove_dir_all(testdir).unwrap();
+        let (cfg, _) = init(testdir.to_string(), &Facts::gather(Os::Linux)).unwrap();
+        assert_eq!(cfg.tasks.len(), 2);
+        assert_eq!(cfg.tasks[0].name, "test");
+        assert_eq!(cfg.tasks[0].pkg.as_ref().unwrap()[0].distri, Distri::Arch);
+        assert_eq!(
+            cfg.tasks[0].pkg.as_ref().unwrap()[0]
+                .install
+                .as_ref()
+                .unwrap()[0],
+            "test"
+        );
+        assert_eq!(
+            cfg.tasks[0].pkg.as_ref().unwrap()[0]
+                .remove
+                .as_ref()
+                .unwrap()[0],
+            "test"
+        );
+        assert_eq!(cfg.tasks[1].name, "test2");
+        assert_eq!(cfg.tasks[1].pkg.as_ref().unwrap()[0].distri, Distri::Arch);
+        assert_eq!(
+            cfg.tasks[1].pkg.as_ref().unwrap()[0]
+                .install
+                .as_ref()
+                .unwrap()[0],
+            "test"
+        );
+        assert_eq!(
+            cfg.tasks[1].pkg.as_ref().unwrap()[0]
+                .remove
+                .as_ref()
+                .unwrap()[0],
+            "test"
+        );
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_include() {
+        let testdir = "unit_config_include_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir_all(PathBuf::from(testdir).join("tasks")).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            include = ["tasks/*.toml"]
+            [[linux.tasks]]
+            name = "main"
+        "#,
+        )
+        .unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks/a.toml"),
+            r#"
+            include = ["../tasks.toml"]
+            [[linux.tasks]]
+            name = "a"
+        "#,
+        )
+        .unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks/b.toml"),
+            r#"
+            [[linux.tasks]]
+            name = "main"
+        "#,
+        )
+        .unwrap();
+        let (cfg, cwd) = init(testdir.to_string(), &Facts::gather(Os::Linux)).unwrap();
+        let names: Vec<_> = cfg.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["main", "a", "main"]);
+        assert_eq!(cfg.tasks[0].file, PathBuf::from("tasks.toml"));
+        assert_eq!(cfg.tasks[2].file, PathBuf::from("tasks/b.toml"));
+        let issues = check(&cfg, &cwd);
+        let dup = issues
+            .iter()
+            .find(|i| i.kind == IssueKind::DuplicateName)
+            .unwrap();
+        assert_eq!(dup.file, "tasks/b.toml");
+        assert_eq!(dup.message, "task is already defined in tasks.toml");
+
+        fs::write(
+            PathBuf::from(testdir).join("tasks/b.toml"),
+            r#"include = ["missing.toml"]"#,
+        )
+        .unwrap();
+        assert!(init(testdir.to_string(), &Facts::gather(Os::Linux)).is_err());
+
+        // the directory of the config is not part of the pattern
+        let dir = PathBuf::from(testdir).join("[host]");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("tasks.toml"), r#"include = ["*.inc.toml"]"#).unwrap();
+        fs::write(
+            dir.join("c.inc.toml"),
+            r#"
+            [[linux.tasks]]
+            name = "c"
+        "#,
+        )
+        .unwrap();
+        let (cfg, _) = init(dir.to_string_lossy().to_string(), &Facts::gather(Os::Linux)).unwrap();
+        assert_eq!(cfg.tasks[0].name, "c");
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_os() {
+        let testdir = "unit_config_os_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir(testdir).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            [[common.tasks]]
+            name = "shell"
+            commands = ["true"]
+            [[common.tasks]]
+            name = "editor"
+            [[linux.tasks]]
+            name = "shell"
+            depends = ["editor"]
+            [[macos.tasks]]
+            name = "shell"
+            commands = ["false"]
+            [[macos.tasks]]
+            name = "brew"
+        "#,
+        )
+        .unwrap();
+        let (cfg, _) = init(testdir.to_string(), &Facts::gather(Os::Linux)).unwrap();
+        let names: Vec<_> = cfg.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["shell", "editor"]);
+        assert_eq!(cfg.tasks[0].commands, Some(vec!["true".into()]));
+        assert_eq!(cfg.tasks[0].depends, Some(vec!["editor".to_string()]));
+        let (cfg, _) = init(testdir.to_string(), &Facts::gather(Os::Macos)).unwrap();
+        let names: Vec<_> = cfg.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["shell", "editor", "brew"]);
+        assert_eq!(cfg.tasks[0].commands, Some(vec!["false".into()]));
+        assert_eq!(cfg.tasks[0].depends, None);
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_when() {
+        let testdir = "unit_config_when_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir(testdir).unwrap();
+        let path = PathBuf::from(testdir).join("tasks.toml");
+        fs::write(
+            &path,
+            r#"
+            [[linux.tasks]]
+            name = "linux"
+            when = 'os == "linux"'
+            commands = ["true", { run = "false", when = 'os == "macos"' }]
+            slink = [
+                ["a", "/a"],
+                { src = "b", dst = "/b", when = 'exists("tasks.toml")' },
+                { src = "c", dst = "/c", when = 'exists("missing.toml")' },
+            ]
+            [[linux.tasks]]
+            name = "macos"
+            when = 'os == "macos"'
+        "#,
+        )
+        .unwrap();
+        let (cfg, cwd) = init(testdir.to_string(), &Facts::gather(Os::Linux)).unwrap();
+        assert_eq!(cfg.tasks.len(), 1);
+        assert_eq!(cfg.tasks[0].commands, Some(vec!["true".into()]));
+        let srcs: Vec<_> = cfg.tasks[0]
+            .slink
+            .iter()
+            .flatten()
+            .map(|l| &l.src)
+            .collect();
+        assert_eq!(
+            srcs,
+            [
+                cwd.join("a").to_str().unwrap(),
+                cwd.join("b").to_str().unwrap()
+            ]
+        );
+
+        fs::write(
+            &path,
+            r#"
+            [[linux.tasks]]
+            name = "broken"
+            when = 'os = "linux"'
+        "#,
+        )
+        .unwrap();
+        let err = init(testdir.to_string(), &Facts::gather(Os::Linux)).unwrap_err();
+        assert!(err.to_string().contains("task broken"));
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_profile() {
+        let testdir = "unit_config_profile_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir(testdir).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            [[profiles]]
+            name = "work"
+            hosts = ["work-*"]
+            tasks = ["editor"]
+            [[profiles]]
+            name = "server"
+            tasks = ["shell"]
+            [[linux.tasks]]
+            name = "shell"
+            [[linux.tasks]]
+            name = "editor"
+            depends = ["shell"]
+            [[linux.tasks]]
+            name = "gui"
+        "#,
+        )
+        .unwrap();
+        let mut facts = Facts::gather(Os::Linux);
+        facts.hostname = "work-laptop".to_string();
+        let names = |cfg: &Cfg| cfg.tasks.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        let (mut cfg, _) = init(testdir.to_string(), &facts).unwrap();
+        cfg.select_profile(None, &facts).unwrap();
+        assert_eq!(cfg.profile.as_deref(), Some("work"));
+        assert_eq!(names(&cfg), ["shell", "editor"]);
+        let (mut cfg, _) = init(testdir.to_string(), &facts).unwrap();
+        cfg.select_profile(Some("server"), &facts).unwrap();
+        assert_eq!(names(&cfg), ["shell"]);
+        assert!(cfg.select_profile(Some("missing"), &facts).is_err());
+        facts.hostname = "home".to_string();
+        let (mut cfg, _) = init(testdir.to_string(), &facts).unwrap();
+        cfg.select_profile(None, &facts).unwrap();
+        assert_eq!(cfg.profile, None);
+        assert_eq!(names(&cfg), ["shell", "editor", "gui"]);
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_tags() {
+        let testdir = "unit_config_tags_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir(testdir).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            [[profiles]]
+            name = "server"
+            tags = ["cli"]
+            [[linux.tasks]]
+            name = "shell"
+            tags = ["cli"]
+            [[linux.tasks]]
+            name = "editor"
+            tags = ["cli", "editor"]
+            [[linux.tasks]]
+            name = "gui"
+        "#,
+        )
+        .unwrap();
+        let facts = Facts::gather(Os::Linux);
+        let names = |cfg: &Cfg| cfg.tasks.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        let (mut cfg, _) = init(testdir.to_string(), &facts).unwrap();
+        assert_eq!(cfg.tags(), ["cli", "editor"]);
+        let filter = TagFilter {
+            tags: vec!["cli".to_string()],
+            skip: vec!["editor".to_string()],
+        };
+        assert!(filter.matches(&cfg.tasks[0]));
+        assert!(!filter.matches(&cfg.tasks[1]));
+        assert!(!filter.matches(&cfg.tasks[2]));
+        cfg.select_profile(Some("server"), &facts).unwrap();
+        assert_eq!(names(&cfg), ["shell", "editor"]);
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_vars() {
+        let testdir = "unit_config_vars_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir(testdir).unwrap();
+        let path = PathBuf::from(testdir).join("tasks.toml");
+        fs::write(
+            PathBuf::from(testdir).join("vars.toml"),
+            r#"
+            [vars]
+            shell = "bash"
+            editor = "vi"
+        "#,
+        )
+        .unwrap();
+        fs::write(
+            &path,
+            r#"
+            include = ["vars.toml"]
+            [vars]
+            shell = "zsh"
+            [[linux.tasks]]
+            name = "shell"
+            path = "{{ shell }}"
+            commands = ["chsh -s {{ shell }}", "echo {{ editor }} {{ os }}"]
+            slink = [["{{ shell }}rc", "{{ home }}/.{{ shell }}rc"]]
+            templates = [["gitconfig", "~/.gitconfig"]]
+            vars = { editor = "nvim" }
+            [[linux.tasks.pkg]]
+            distri = "Arch"
+            install = ["{{ shell }}"]
+        "#,
+        )
+        .unwrap();
+        fs::create_dir(PathBuf::from(testdir).join("zsh")).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("zsh/gitconfig"),
+            "editor = {{ editor }}\n",
+        )
+        .unwrap();
+        let facts = Facts::gather(Os::Linux);
+        let (cfg, cwd) = init(testdir.to_string(), &facts).unwrap();
+        let task = &cfg.tasks[0];
+        let tpl = &task.templates.as_ref().unwrap()[0];
+        assert_eq!(tpl.dst, facts.home.join(".gitconfig").to_string_lossy());
+        assert_eq!(tpl.content, "editor = nvim\n");
+        assert_eq!(task.path.as_deref(), Some("zsh"));
+        assert_eq!(
+            task.commands,
+            Some(vec!["chsh -s zsh".into(), "echo nvim linux".into()])
+        );
+        assert_eq!(
+            task.pkg.as_ref().unwrap()[0].install,
+            Some(vec!["zsh".to_string()])
+        );
+        let link = &task.slink.as_ref().unwrap()[0];
+        assert_eq!(link.src, cwd.join("zsh/zshrc").to_string_lossy());
+        assert_eq!(link.dst, facts.home.join(".zshrc").to_string_lossy());
+
+        fs::write(
+            &path,
+            r#"
+            [[linux.tasks]]
+            name = "broken"
+            commands = ["echo {{ undefined }}"]
+        "#,
+        )
+        .unwrap();
+        let err = init(testdir.to_string(), &facts).unwrap_err();
+        assert!(err.to_string().contains("undefined variable undefined"));
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_glob() {
+        let testdir = "unit_config_glob_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        fs::create_dir_all(PathBuf::from(testdir).join("scripts/bin")).unwrap();
+        for name in ["b.sh", "a.sh", "notes.txt"] {
+            fs::write(PathBuf::from(testdir).join("scripts/bin").join(name), "").unwrap();
+        }
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            [[linux.tasks]]
+            name = "scripts"
+            path = "scripts"
+            slink = [
+                ["bin/*.sh", "~/.local/bin"],
+                { src = "bin/*.txt", dst = "~/docs", mode = "copy" },
+                ["bin/notes.txt", "~/notes.txt"],
+            ]
+        "#,
+        )
+        .unwrap();
+        let facts = Facts::gather(Os::Linux);
+        let (cfg, cwd) = init(testdir.to_string(), &facts).unwrap();
+        let links: Vec<_> = cfg.tasks[0]
+            .slink
+            .iter()
+            .flatten()
+            .map(|l| (l.src.clone(), l.dst.clone()))
+            .collect();
+        let pair = |src: &str, dst: &str| {
+            (
+                cwd.join("scripts").join(src).to_string_lossy().to_string(),
+                facts.home.join(dst).to_string_lossy().to_string(),
+            )
+        };
+        assert_eq!(
+            links,
+            vec![
+                pair("bin/a.sh", ".local/bin/a.sh"),
+                pair("bin/b.sh", ".local/bin/b.sh"),
+                pair("bin/notes.txt", "docs/notes.txt"),
+                pair("bin/notes.txt", "notes.txt"),
+            ]
+        );
+        // outside the task path, so not relative to it
+        let bin = cwd.join("scripts/bin");
+        fs::create_dir(PathBuf::from(testdir).join("elsewhere")).unwrap();
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            format!(
+                r#"
+            [[linux.tasks]]
+            name = "scripts"
+            path = "elsewhere"
+            slink = [["{}/*.sh", "~/.local/bin"]]
+        "#,
+                bin.display()
+            ),
+        )
+        .unwrap();
+        let (cfg, _) = init(testdir.to_string(), &facts).unwrap();
+        let srcs: Vec<_> = cfg.tasks[0]
+            .slink
+            .iter()
+            .flatten()
+            .map(|l| &l.src)
+            .collect();
+        assert_eq!(
+            srcs,
+            [bin.join("a.sh"), bin.join("b.sh")]
+                .map(|p| p.to_string_lossy().to_string())
+                .iter()
+                .collect::<Vec<_>>()
+        );
+        fs::write(
+            PathBuf::from(testdir).join("tasks.toml"),
+            r#"
+            [[linux.tasks]]
+            name = "scripts"
+            path = "scripts"
+            slink = [["bin/*.py", "~/.local/bin"]]
+        "#,
+        )
+        .unwrap();
+        let error = init(testdir.to_string(), &facts).unwrap_err();
+        assert!(format!("{:#}", error).contains("slink bin/*.py: the pattern matches nothing"));
+        remove_dir_all(testdir).unwrap();
+    }
+    #[test]
+    fn test_order() {
+        let task = |name: &str, depends: &[&str]| Task {
+            name: name.to_string(),
+            depends: Some(depends.iter().map(|d| d.to_string()).collect()),
+            ..Default::default()
+        };
+        let cfg = Cfg {
+            tasks: vec![
+                task("zsh", &["fonts", "git"]),
+                task("git", &[]),
+                task("fonts", &["git"]),
+                task("a", &["b"]),
+                task("b", &["a"]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(cfg.order(&[0, 1, 2]), [1, 2, 0]);
+        assert_eq!(cfg.order(&[0, 1]), [1, 2, 0]);
+        assert_eq!(cfg.order(&[0]), [1, 2, 0]);
+        assert_eq!(cfg.order(&[2, 1]), [1, 2]);
+        assert_eq!(cfg.order(&[3, 4]), [4, 3]);
+        assert!(cfg.depends_on(0, &["git".to_string()]));
+        assert!(!cfg.depends_on(2, &["zsh".to_string()]));
     }
     #[test]
     fn test_example() {
-        let testdir = "unit_config_test";
+        let testdir = "unit_config_example_test";
         let hook = std::panic::take_hook();
         panic::set_hook(Box::new(move |info| {
-            remove_dir_all(testdir).unwrap();
+            let _ = remove_dir_all(testdir);
             hook(info);
         }));
         fs::create_dir(testdir).unwrap();
         let path = PathBuf::from(testdir).join("tasks.yaml");
         let cfg = Config {
-            linux: Some(LinuxConfig {
+            linux: Some(OsConfig {
                 tasks: vec![
                     Task {
                         name: "test".to_string(),
@@ -172,6 +1206,8 @@ mod tests {
                         }]),
                         commands: None,
                         slink: None,
+                        depends: None,
+                        ..Default::default()
                     },
                     Task {
                         name: "test2".to_string(),
@@ -181,14 +1217,18 @@ mod tests {
                             install: Some(vec!["test".to_string()]),
                             remove: Some(vec!["test".to_string()]),
                         }]),
-                        commands: Some(vec!["test".to_string()]),
-                        slink: Some(vec![("test".to_string(), "test".to_string())]),
+                        commands: Some(vec!["test".into()]),
+                        slink: Some(vec![("test", "test").into()]),
+                        depends: Some(vec!["test".to_string()]),
+                        ..Default::default()
                     },
                 ],
             }),
+            ..Default::default()
         };
         let mut xf = XFile::default().path(path.to_str().unwrap());
         xf.inner = cfg;
         xf.save().unwrap();
+        remove_dir_all(testdir).unwrap();
     }
 }
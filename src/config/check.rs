@@ -0,0 +1,392 @@
+use super::{Cfg, Distri, Profile, Symlink, Task};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum IssueKind {
+    DuplicateName,
+    EmptyTask,
+    UnknownDistri,
+    MissingSource,
+    InvalidPath,
+    UnknownDependency,
+    DependencyCycle,
+    UnknownTask,
+    InvalidPattern,
+    InvalidTemplate,
+    /// The config could not be loaded at all
+    InvalidConfig,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub file: String,
+    pub task: String,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(severity: Severity, kind: IssueKind, task: &Task, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            file: task.file.display().to_string(),
+            task: task.name.clone(),
+            message,
+        }
+    }
+    fn profile(kind: IssueKind, profile: &Profile, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind,
+            file: profile.file.display().to_string(),
+            task: format!("profile {}", profile.name),
+            message,
+        }
+    }
+    /// An error preventing the config in `dir` from loading.
+    pub fn load(dir: &str, error: &anyhow::Error) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind: IssueKind::InvalidConfig,
+            file: Path::new(dir).join("tasks.toml").display().to_string(),
+            task: String::new(),
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}] {}: {}",
+            self.file, self.severity, self.kind, self.task, self.message
+        )
+    }
+}
+
+/// Validates a loaded config, `cwd` being the directory it was loaded from.
+pub fn check(cfg: &Cfg, cwd: &Path) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (i, task) in cfg.tasks.iter().enumerate() {
+        if let Some(first) = cfg.tasks[..i].iter().find(|t| t.name == task.name) {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::DuplicateName,
+                task,
+                format!("task is already defined in {}", first.file.display()),
+            ));
+        }
+        check_task(task, cwd, &mut issues);
+        for dep in task.depends.iter().flatten() {
+            if !cfg.tasks.iter().any(|t| &t.name == dep) {
+                issues.push(Issue::new(
+                    Severity::Error,
+                    IssueKind::UnknownDependency,
+                    task,
+                    format!("depends on undefined task {}", dep),
+                ));
+            }
+        }
+    }
+    for profile in &cfg.profiles {
+        for name in profile.tasks.iter().flatten() {
+            if !cfg.tasks.iter().any(|t| &t.name == name) {
+                issues.push(Issue::profile(
+                    IssueKind::UnknownTask,
+                    profile,
+                    format!("selects undefined task {}", name),
+                ));
+            }
+        }
+        for host in profile.hosts.iter().flatten() {
+            if let Err(e) = glob::Pattern::new(host) {
+                issues.push(Issue::profile(
+                    IssueKind::InvalidPattern,
+                    profile,
+                    format!("host pattern {}: {}", host, e),
+                ));
+            }
+        }
+    }
+    if let Some(cycle) = find_cycle(cfg) {
+        let task = cfg.tasks.iter().find(|t| t.name == cycle[0]).unwrap();
+        issues.push(Issue::new(
+            Severity::Error,
+            IssueKind::DependencyCycle,
+            task,
+            format!("dependency cycle {}", cycle.join(" -> ")),
+        ));
+    }
+    issues
+}
+
+fn check_task(task: &Task, cwd: &Path, issues: &mut Vec<Issue>) {
+    let is_empty = |v: Option<usize>| v.unwrap_or(0) == 0;
+    if is_empty(task.pkg.as_ref().map(Vec::len))
+        && is_empty(task.commands.as_ref().map(Vec::len))
+        && is_empty(task.slink.as_ref().map(Vec::len))
+        && is_empty(task.templates.as_ref().map(Vec::len))
+        && is_empty(task.stow.as_ref().map(Vec::len))
+    {
+        issues.push(Issue::new(
+            Severity::Warning,
+            IssueKind::EmptyTask,
+            task,
+            "task has no packages, commands, symlinks, templates or stow trees".to_string(),
+        ));
+    }
+    for (i, pkg) in task.pkg.iter().flatten().enumerate() {
+        if pkg.distri == Distri::Unknown {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::UnknownDistri,
+                task,
+                format!("package entry {} targets an unknown distribution", i),
+            ));
+        }
+    }
+    if let Some(path) = &task.path {
+        if !cwd.join(path).is_dir() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::InvalidPath,
+                task,
+                format!("path {} is not a directory", path),
+            ));
+        }
+    } else if (task.commands.iter().chain(&task.uninstall_commands)).any(|c| !c.is_empty())
+        && !cwd.join(&task.name).is_dir()
+    {
+        issues.push(Issue::new(
+            Severity::Error,
+            IssueKind::InvalidPath,
+            task,
+            format!(
+                "commands run in {} which is not a directory",
+                cwd.join(&task.name).display()
+            ),
+        ));
+    }
+    for Symlink { src, dst, .. } in task.slink.iter().flatten() {
+        if std::fs::symlink_metadata(src).is_err() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::MissingSource,
+                task,
+                format!("symlink source {} does not exist", src),
+            ));
+        }
+        if !Path::new(dst).is_absolute() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::InvalidPath,
+                task,
+                format!("symlink destination {} is not an absolute path", dst),
+            ));
+        }
+    }
+    for tpl in task.templates.iter().flatten() {
+        if !Path::new(&tpl.src).is_file() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::MissingSource,
+                task,
+                format!("template source {} is not a file", tpl.src),
+            ));
+        } else if let Some(error) = &tpl.error {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::InvalidTemplate,
+                task,
+                error.clone(),
+            ));
+        }
+        if !Path::new(&tpl.dst).is_absolute() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::InvalidPath,
+                task,
+                format!("template destination {} is not an absolute path", tpl.dst),
+            ));
+        }
+    }
+    for stow in task.stow.iter().flatten() {
+        if !Path::new(&stow.src).is_dir() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::MissingSource,
+                task,
+                format!("stow source {} is not a directory", stow.src),
+            ));
+        }
+        if !Path::new(&stow.dst).is_absolute() {
+            issues.push(Issue::new(
+                Severity::Error,
+                IssueKind::InvalidPath,
+                task,
+                format!("stow destination {} is not an absolute path", stow.dst),
+            ));
+        }
+        for pattern in stow.ignore.iter().flatten() {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                issues.push(Issue::new(
+                    Severity::Error,
+                    IssueKind::InvalidPattern,
+                    task,
+                    format!("ignore pattern {}: {}", pattern, e),
+                ));
+            }
+        }
+    }
+}
+
+fn find_cycle(cfg: &Cfg) -> Option<Vec<String>> {
+    fn visit<'a>(
+        name: &'a str,
+        cfg: &'a Cfg,
+        done: &mut Vec<&'a str>,
+        stack: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        if let Some(pos) = stack.iter().position(|n| *n == name) {
+            let mut cycle: Vec<_> = stack[pos..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.to_string());
+            return Some(cycle);
+        }
+        if done.contains(&name) {
+            return None;
+        }
+        stack.push(name);
+        for task in cfg.tasks.iter().filter(|t| t.name == name) {
+            for dep in task.depends.iter().flatten() {
+                if let Some(cycle) = visit(dep, cfg, done, stack) {
+                    return Some(cycle);
+                }
+            }
+        }
+        stack.pop();
+        done.push(name);
+        None
+    }
+    let mut done = Vec::new();
+    cfg.tasks
+        .iter()
+        .find_map(|t| visit(&t.name, cfg, &mut done, &mut Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Package, Template};
+
+    fn task(name: &str) -> Task {
+        Task {
+            name: name.to_string(),
+            commands: Some(vec!["true".into()]),
+            path: Some(".".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(cfg: &Cfg) -> Vec<IssueKind> {
+        check(cfg, &std::env::current_dir().unwrap())
+            .into_iter()
+            .map(|i| i.kind)
+            .collect()
+    }
+
+    #[test]
+    fn valid() {
+        let mut b = task("b");
+        b.depends = Some(vec!["a".to_string()]);
+        let cfg = Cfg {
+            tasks: vec![task("a"), b],
+            ..Default::default()
+        };
+        assert!(kinds(&cfg).is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        let mut empty = task("empty");
+        empty.commands = None;
+        let mut bad = task("bad");
+        bad.path = Some("no_such_dir".to_string());
+        bad.pkg = Some(vec![Package::default()]);
+        bad.slink = Some(vec![("no_such_src", "dst").into()]);
+        bad.templates = Some(vec![
+            Template {
+                src: "no_such_src".to_string(),
+                dst: "/dst".to_string(),
+                ..Default::default()
+            },
+            Template {
+                src: "Cargo.toml".to_string(),
+                dst: "/dst".to_string(),
+                error: Some("undefined variable".to_string()),
+                ..Default::default()
+            },
+        ]);
+        bad.depends = Some(vec!["missing".to_string()]);
+        let cfg = Cfg {
+            tasks: vec![task("a"), task("a"), empty, bad],
+            profiles: vec![Profile {
+                name: "p".to_string(),
+                hosts: Some(vec!["[".to_string()]),
+                tasks: Some(vec!["a".to_string(), "b".to_string()]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            kinds(&cfg),
+            vec![
+                IssueKind::DuplicateName,
+                IssueKind::EmptyTask,
+                IssueKind::UnknownDistri,
+                IssueKind::InvalidPath,
+                IssueKind::MissingSource,
+                IssueKind::InvalidPath,
+                IssueKind::MissingSource,
+                IssueKind::InvalidTemplate,
+                IssueKind::UnknownDependency,
+                IssueKind::UnknownTask,
+                IssueKind::InvalidPattern,
+            ]
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let mut a = task("a");
+        a.depends = Some(vec!["b".to_string()]);
+        let mut b = task("b");
+        b.depends = Some(vec!["a".to_string()]);
+        let cfg = Cfg {
+            tasks: vec![a, b],
+            ..Default::default()
+        };
+        let issues = check(&cfg, &std::env::current_dir().unwrap());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "dependency cycle a -> b -> a");
+    }
+
+    #[test]
+    fn unknown_distri() {
+        let pkg: Package = serde_json::from_str(r#"{"distri": "Gentoo"}"#).unwrap();
+        assert_eq!(pkg.distri, Distri::Unknown);
+    }
+}
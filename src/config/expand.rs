@@ -0,0 +1,107 @@
+//! Expansion of `~`, `$NAME` and `${NAME}` in paths.
+//!
+//! `${NAME:-default}` falls back to `default` when `NAME` is unset or empty,
+//! and the XDG base directories fall back to their standard location under
+//! the home directory. `$$` is a literal `$`.
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+
+const XDG_DEFAULTS: [(&str, &str); 4] = [
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_CACHE_HOME", ".cache"),
+];
+
+/// Where an unset `HOME` or XDG base directory falls back to under `home`.
+pub(super) fn env_default(name: &str, home: &str) -> Option<String> {
+    if name == "HOME" {
+        return Some(home.to_string());
+    }
+    let (_, dir) = XDG_DEFAULTS.iter().find(|(xdg, _)| *xdg == name)?;
+    Some(format!("{}/{}", home, dir))
+}
+
+/// Expands a path, `home` being used for `~` and when `HOME` is unset.
+pub fn expand(path: &str, home: &Path) -> Result<String> {
+    let home = home.to_string_lossy();
+    let mut out = String::new();
+    let mut rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            out.push_str(&home);
+            rest
+        }
+        _ => path,
+    };
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, default) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or(anyhow!("unterminated ${{ in {:?}", path))?;
+            rest = &braced[end + 1..];
+            match braced[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&braced[..end], None),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            (name, None)
+        };
+        if name.is_empty() {
+            bail!("missing variable name after $ in {:?}", path);
+        }
+        let value = std::env::var(name).ok().filter(|v| !v.is_empty());
+        out.push_str(&match (value, default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => expand(default, Path::new(home.as_ref()))?,
+            (None, None) => env_default(name, &home).ok_or(anyhow!(
+                "undefined environment variable {} in {:?}",
+                name,
+                path
+            ))?,
+        });
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansions() {
+        let home = Path::new("/home/me");
+        let expand = |path| expand(path, home).unwrap();
+        assert_eq!(expand("~"), "/home/me");
+        assert_eq!(expand("~/.zshrc"), "/home/me/.zshrc");
+        assert_eq!(expand("a~b"), "a~b");
+        assert_eq!(expand("~other/x"), "~other/x");
+        assert_eq!(expand("${EASYCFG_UNSET_VARIABLE:-~/x}/$$"), "/home/me/x/$");
+        std::env::set_var("EASYCFG_EXPAND_TEST", "value");
+        assert_eq!(
+            expand("/a/$EASYCFG_EXPAND_TEST/${EASYCFG_EXPAND_TEST}.d"),
+            "/a/value/value.d"
+        );
+        if std::env::var("XDG_DATA_HOME").is_err() {
+            assert_eq!(
+                expand("$XDG_DATA_HOME/fonts"),
+                "/home/me/.local/share/fonts"
+            );
+        }
+        for path in ["$EASYCFG_UNSET_VARIABLE", "${HOME", "/$/x"] {
+            assert!(super::expand(path, home).is_err(), "{}", path);
+        }
+    }
+}
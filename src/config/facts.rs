@@ -0,0 +1,41 @@
+use super::{get_release, os_rel::get_version, Distri, Os};
+use std::path::PathBuf;
+
+/// What is known about the machine easycfg runs on
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub os: Os,
+    pub distri: Distri,
+    pub release: String,
+    pub arch: String,
+    pub hostname: String,
+    pub home: PathBuf,
+    /// The config directory, relative paths are resolved against it
+    pub dir: PathBuf,
+}
+
+impl Facts {
+    pub fn gather(os: Os) -> Self {
+        Self {
+            os,
+            distri: get_release(),
+            release: get_version(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            home: dirs::home_dir().unwrap_or_default(),
+            dir: PathBuf::new(),
+        }
+    }
+    pub fn env(&self, name: &str) -> String {
+        std::env::var(name).unwrap_or_default()
+    }
+    pub fn has_command(&self, name: &str) -> bool {
+        std::env::var_os("PATH")
+            .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+    }
+    pub fn file_exists(&self, path: &str) -> bool {
+        super::expand::expand(path, &self.home).is_ok_and(|path| self.dir.join(path).exists())
+    }
+}
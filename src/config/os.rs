@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::Display, clap::ValueEnum)]
+#[strum(serialize_all = "lowercase")]
+pub enum Os {
+    Linux,
+    Macos,
+    Freebsd,
+}
+
+impl Os {
+    /// The operating system easycfg was built for, if it has a config section
+    pub fn current() -> Option<Self> {
+        match std::env::consts::OS {
+            "linux" => Some(Os::Linux),
+            "macos" => Some(Os::Macos),
+            "freebsd" => Some(Os::Freebsd),
+            _ => None,
+        }
+    }
+}
@@ -6,7 +6,7 @@ const OS_RELEASE_PATHS: [&str; 2] = ["/etc/os-release", "/usr/lib/os-release"];
 const OS_RELEASE_ID_FLAGS: [(Distri, &str); 2] =
     [(Distri::Arch, "arch"), (Distri::OpenSUSE, "opensuse")];
 
-pub fn get_release() -> Distri {
+fn os_release() -> Vec<(String, String)> {
     let reg = regex::Regex::new(
         r#"^(?P<key>[a-zA-Z0-9_]+)=(?:["']?[[:space:]]*)(?P<value>.*?)(?:[[:space:]]*["']?)$"#,
     )
@@ -17,7 +17,7 @@ pub fn get_release() -> Distri {
             continue;
         }
         let file = file.unwrap();
-        let mut distri = Distri::Unknown;
+        let mut fields = Vec::new();
         for line in file.lines() {
             let caps = reg.captures(line.trim());
             if caps.is_none() {
@@ -26,17 +26,32 @@ pub fn get_release() -> Distri {
             let caps = caps.unwrap();
             let key = caps.name("key").unwrap().as_str();
             let value = caps.name("value").unwrap().as_str();
-            if key == "ID" {
-                for (d, id) in OS_RELEASE_ID_FLAGS.iter() {
-                    if value.contains(id) {
-                        distri = *d;
-                        break;
-                    }
+            debug!("{}: {}", key, value);
+            fields.push((key.to_string(), value.to_string()));
+        }
+        return fields;
+    }
+    Vec::new()
+}
+
+pub fn get_release() -> Distri {
+    for (key, value) in os_release() {
+        if key == "ID" {
+            for (d, id) in OS_RELEASE_ID_FLAGS.iter() {
+                if value.contains(id) {
+                    return *d;
                 }
             }
-            debug!("{}: {}", key, value);
         }
-        return distri;
     }
     Distri::Unknown
 }
+
+/// The `VERSION_ID` of the distribution, empty for rolling releases
+pub fn get_version() -> String {
+    os_release()
+        .into_iter()
+        .find(|(key, _)| key == "VERSION_ID")
+        .map(|(_, value)| value)
+        .unwrap_or_default()
+}
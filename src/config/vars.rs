@@ -0,0 +1,117 @@
+//! `{{ name }}` interpolation in task fields and templates.
+//!
+//! Names are looked up in the task's `vars`, then the config's `vars`, then
+//! the facts: `home`, `hostname`, `distri`, `release`, `arch`, `os` and
+//! `env.NAME`, unset XDG base directories falling back to their standard
+//! location. Variables may reference each other.
+use super::Facts;
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+
+pub type Vars = BTreeMap<String, serde_json::Value>;
+
+pub struct Context<'a> {
+    pub facts: &'a Facts,
+    pub global: &'a Vars,
+    pub local: Option<&'a Vars>,
+}
+
+impl Context<'_> {
+    /// Replaces every `{{ name }}` in `text`.
+    pub fn render(&self, text: &str) -> Result<String> {
+        self.render_with(text, &mut Vec::new())
+    }
+    fn render_with(&self, text: &str, stack: &mut Vec<String>) -> Result<String> {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or(anyhow!("unterminated {{{{ in {:?}", text))?;
+            let name = rest[start + 2..start + end].trim();
+            out.push_str(&self.lookup(name, stack)?);
+            rest = &rest[start + end + 2..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+    fn lookup(&self, name: &str, stack: &mut Vec<String>) -> Result<String> {
+        let var = self
+            .local
+            .and_then(|vars| vars.get(name))
+            .or(self.global.get(name));
+        if let Some(value) = var {
+            if stack.iter().any(|n| n == name) {
+                bail!("variable {} references itself", name);
+            }
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            stack.push(name.to_string());
+            let value = self.render_with(&value, stack);
+            stack.pop();
+            return value;
+        }
+        let facts = self.facts;
+        Ok(match name {
+            "home" => facts.home.to_string_lossy().to_string(),
+            "hostname" => facts.hostname.clone(),
+            "distri" => facts.distri.to_string(),
+            "release" => facts.release.clone(),
+            "arch" => facts.arch.clone(),
+            "os" => facts.os.to_string(),
+            _ => match name.strip_prefix("env.") {
+                Some(env) => match std::env::var(env).ok().filter(|v| !v.is_empty()) {
+                    Some(value) => value,
+                    None => super::expand::env_default(env, &facts.home.to_string_lossy())
+                        .ok_or(anyhow!("undefined environment variable {}", env))?,
+                },
+                None => bail!("undefined variable {}", name),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Os;
+
+    #[test]
+    fn render() {
+        let mut facts = Facts::gather(Os::Linux);
+        facts.hostname = "laptop".to_string();
+        let global: Vars = serde_json::from_str(
+            r#"{"email": "me@{{ hostname }}", "size": 12, "font": "mono {{ size }}"}"#,
+        )
+        .unwrap();
+        let local: Vars = serde_json::from_str(r#"{"size": 14}"#).unwrap();
+        let ctx = Context {
+            facts: &facts,
+            global: &global,
+            local: Some(&local),
+        };
+        assert_eq!(
+            ctx.render("{{email}}: {{ font }}").unwrap(),
+            "me@laptop: mono 14"
+        );
+        assert_eq!(ctx.render("{{ os }}").unwrap(), "linux");
+        assert!(ctx.render("{{ missing }}").is_err());
+        assert!(ctx.render("{{ env.EASYCFG_UNSET_VARIABLE }}").is_err());
+        let cache = std::env::var("XDG_CACHE_HOME")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or(format!("{}/.cache", facts.home.display()));
+        assert_eq!(ctx.render("{{ env.XDG_CACHE_HOME }}").unwrap(), cache);
+        assert!(ctx.render("{{ email").is_err());
+        let looped: Vars = serde_json::from_str(r#"{"a": "{{ b }}", "b": "{{ a }}"}"#).unwrap();
+        let ctx = Context {
+            facts: &facts,
+            global: &looped,
+            local: None,
+        };
+        assert!(ctx.render("{{ a }}").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Conditions on tasks and steps, e.g.
+//! `distri == "Arch" && (hostname =~ "^work-" || exists("~/.work"))`.
+//!
+//! Values are string literals or facts: `hostname`, `distri`, `release`,
+//! `arch`, `os` and `env.NAME`. A value on its own is true when not empty.
+//! `command("name")` tests for an executable in `PATH` and `exists("path")`
+//! for a file or directory, relative paths being resolved against the config
+//! directory.
+use super::Facts;
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Ident(String),
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Match,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '"' | '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(ch) => s.push(ch),
+                        None => bail!("unterminated string"),
+                    }
+                }
+                Token::Str(s)
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Ne,
+            '!' => Token::Not,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Eq,
+            '=' if chars.next_if_eq(&'~').is_some() => Token::Match,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(ch) =
+                    chars.next_if(|ch| ch.is_alphanumeric() || *ch == '_' || *ch == '.')
+                {
+                    s.push(ch);
+                }
+                Token::Ident(s)
+            }
+            c => bail!("unexpected character {:?}", c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    facts: &'a Facts,
+    /// Cleared while parsing an operand which cannot change the result
+    eval: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn next(&mut self) -> Result<Token> {
+        let token = self.peek().cloned().ok_or(anyhow!("unexpected end"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+    /// Parses with `parse`, only evaluating the facts when `eval` is set.
+    fn operand(&mut self, eval: bool, parse: fn(&mut Self) -> Result<bool>) -> Result<bool> {
+        let outer = self.eval;
+        self.eval = outer && eval;
+        let value = parse(self);
+        self.eval = outer;
+        value
+    }
+    fn or(&mut self) -> Result<bool> {
+        let mut value = self.and()?;
+        while self.eat(&Token::Or) {
+            let right = self.operand(!value, Self::and)?;
+            value = value || right;
+        }
+        Ok(value)
+    }
+    fn and(&mut self) -> Result<bool> {
+        let mut value = self.unary()?;
+        while self.eat(&Token::And) {
+            let right = self.operand(value, Self::unary)?;
+            value = value && right;
+        }
+        Ok(value)
+    }
+    fn unary(&mut self) -> Result<bool> {
+        if self.eat(&Token::Not) {
+            return Ok(!self.unary()?);
+        }
+        if self.eat(&Token::LParen) {
+            let value = self.or()?;
+            if !self.eat(&Token::RParen) {
+                bail!("expected )");
+            }
+            return Ok(value);
+        }
+        let left = self.value()?;
+        let op = match self.peek() {
+            Some(op @ (Token::Eq | Token::Ne | Token::Match)) => op.clone(),
+            _ => return Ok(!left.is_empty()),
+        };
+        self.pos += 1;
+        let right = self.value()?;
+        Ok(match op {
+            _ if !self.eval => false,
+            Token::Eq => left == right,
+            Token::Ne => left != right,
+            _ => regex::Regex::new(&right)
+                .map_err(|e| anyhow!("invalid regex {:?}: {}", right, e))?
+                .is_match(&left),
+        })
+    }
+    fn value(&mut self) -> Result<String> {
+        let ident = match self.next()? {
+            Token::Str(s) => return Ok(s),
+            Token::Ident(ident) => ident,
+            token => bail!("unexpected {:?}", token),
+        };
+        if self.eat(&Token::LParen) {
+            let arg = match self.next()? {
+                Token::Str(s) => s,
+                token => bail!("expected a string argument, found {:?}", token),
+            };
+            if !self.eat(&Token::RParen) {
+                bail!("expected )");
+            }
+            let found = match ident.as_str() {
+                "command" | "exists" if !self.eval => false,
+                "command" => self.facts.has_command(&arg),
+                "exists" => self.facts.file_exists(&arg),
+                _ => bail!("unknown function {}", ident),
+            };
+            return Ok(if found {
+                "true".to_string()
+            } else {
+                String::new()
+            });
+        }
+        Ok(match ident.as_str() {
+            "hostname" => self.facts.hostname.clone(),
+            "distri" => self.facts.distri.to_string(),
+            "release" => self.facts.release.clone(),
+            "arch" => self.facts.arch.clone(),
+            "os" => self.facts.os.to_string(),
+            _ => match ident.strip_prefix("env.") {
+                Some(name) => self.facts.env(name),
+                None => bail!("unknown fact {}", ident),
+            },
+        })
+    }
+}
+
+/// Evaluates a condition against the facts of this machine.
+pub fn eval(expr: &str, facts: &Facts) -> Result<bool> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        facts,
+        eval: true,
+    };
+    let value = parser.or()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {:?}", token);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Distri, Os};
+
+    fn facts() -> Facts {
+        Facts {
+            os: Os::Linux,
+            distri: Distri::Arch,
+            release: String::new(),
+            arch: "x86_64".to_string(),
+            hostname: "work-laptop".to_string(),
+            home: std::env::current_dir().unwrap(),
+            dir: std::env::current_dir().unwrap().join("src"),
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let facts = facts();
+        let eval = |expr| eval(expr, &facts).unwrap();
+        assert!(eval(r#"distri == "Arch""#));
+        assert!(eval(r#"os == "linux" && arch != "aarch64""#));
+        assert!(eval(r#"hostname =~ "^work-""#));
+        assert!(!eval(r#"!(hostname =~ "laptop$") || release"#));
+        assert!(eval(r#"exists("~/Cargo.toml") && command("sh")"#));
+        assert!(eval(r#"exists("main.rs") && !exists("Cargo.toml")"#));
+        assert!(!eval(r#"env.EASYCFG_UNSET_VARIABLE"#));
+        // the operands deciding nothing are not evaluated
+        assert!(eval(r#"os == "linux" || "a" =~ "(""#));
+        assert!(!eval(r#"os == "macos" && "a" =~ "(""#));
+        assert!(!eval(r#"os == "macos" && ("a" =~ "(" || "b" =~ ")")"#));
+    }
+
+    #[test]
+    fn errors() {
+        let facts = facts();
+        for expr in [
+            r#"distri == "Arch"#,
+            "distri ==",
+            "(distri",
+            "kernel",
+            r#"which("sh")"#,
+            r#"distri "Arch""#,
+        ] {
+            assert!(eval(expr, &facts).is_err(), "{}", expr);
+        }
+    }
+}
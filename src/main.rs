@@ -1,33 +1,245 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use easycfg::{config, server, tui::Tui};
+use easycfg::server::runlog::{RunLog, RunLogWriter, KEEP_RUNS};
+use easycfg::server::step::Step;
+use easycfg::{
+    args::{Command, OutputFormat},
+    config, server,
+    tui::{LogBuffer, Tui},
+};
+use std::process::ExitCode;
 use tracing::info;
-use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
 
-fn setup_log() -> Result<()> {
-    // let file = std::fs::OpenOptions::new()
-    //     .create(true)
-    //     .append(true)
-    //     .open("./ecfg.log")?;
-    let filter: EnvFilter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env()?;
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
+/// Logs to stderr, or to `buffer` while the interface owns the terminal, and
+/// to the run log if any.
+fn setup_log(file: Option<RunLogWriter>, buffer: Option<LogBuffer>) -> Result<()> {
+    let filter = |level: LevelFilter| {
+        EnvFilter::builder()
+            .with_default_directive(level.into())
+            .from_env()
+    };
+    let stderr = match buffer {
+        Some(_) => None,
+        None => Some(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_writer(std::io::stderr)
+                .with_filter(filter(LevelFilter::INFO)?),
+        ),
+    };
+    let buffer = match buffer {
+        Some(buffer) => Some(buffer.with_filter(filter(LevelFilter::DEBUG)?)),
+        None => None,
+    };
+    let file = match file {
+        Some(file) => Some(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_ansi(false)
+                .with_writer(move || file.clone())
+                .with_filter(filter(LevelFilter::INFO)?),
+        ),
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(buffer)
+        .with(file)
         .init();
     Ok(())
 }
 
-fn main() -> Result<()> {
-    setup_log()?;
+fn check(directory: String, facts: &config::Facts, json: bool) -> Result<ExitCode> {
+    let issues = match config::init(directory.clone(), facts) {
+        Ok((cfg, cwd)) => config::check(&cfg, &cwd),
+        Err(e) => vec![config::Issue::load(&directory, &e)],
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&issues)?);
+    } else {
+        for issue in &issues {
+            println!("{}", issue);
+        }
+    }
+    if issues.iter().any(|i| i.severity == config::Severity::Error) {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn status(server: &mut server::Server, json: bool) -> Result<ExitCode> {
+    let drifts = server.drift();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&drifts)?);
+    } else if drifts.is_empty() {
+        println!("no drift in {} task(s)", server.cfg.tasks.len());
+    } else {
+        for drift in &drifts {
+            println!("{}", drift);
+        }
+    }
+    if drifts.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+fn list(cfg: &config::Cfg) -> Result<ExitCode> {
+    let state = match server::state::State::default_path() {
+        Some(path) => server::state::State::load(&path)?,
+        None => Default::default(),
+    };
+    for task in &cfg.tasks {
+        let tags = task.tags.iter().flatten().map(|t| format!(" #{}", t));
+        println!(
+            "{} [{}]{}",
+            task.name,
+            state.status(task),
+            tags.collect::<String>()
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_steps(steps: &[Step]) {
+    for step in steps {
+        let (out, err) = step.lines();
+        for line in out {
+            println!("{}", line);
+        }
+        for line in err {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Runs the named tasks in order, or every task not applied yet unless
+/// forced, stopping at the first failure.
+fn run(
+    server: &mut server::Server,
+    names: Vec<String>,
+    all: bool,
+    force: bool,
+    format: OutputFormat,
+) -> Result<ExitCode> {
+    let indices = if all {
+        let indices = match force {
+            true => (0..server.cfg.tasks.len()).collect(),
+            false => server.outdated(),
+        };
+        let applied = server.cfg.tasks.len() - indices.len();
+        if applied > 0 && format == OutputFormat::Text {
+            println!(
+                "skipping {} task(s) unchanged since their last run, --force runs them",
+                applied
+            );
+        }
+        indices
+    } else {
+        let position = |name: &String| {
+            let tasks = &server.cfg.tasks;
+            tasks
+                .iter()
+                .position(|t| t.name == *name)
+                .ok_or(anyhow!("unknown task {}", name))
+        };
+        names.iter().map(position).collect::<Result<Vec<_>>>()?
+    };
+    server.ask_sudo(&indices)?;
+    let total = indices.len();
+    let mut records = Vec::new();
+    let mut code = ExitCode::SUCCESS;
+    for (n, index) in indices.into_iter().enumerate() {
+        if format == OutputFormat::Text {
+            println!("==> {} ({}/{})", server.cfg.tasks[index].name, n + 1, total);
+        }
+        let (steps, result) = server.exec_by_index(index);
+        match format {
+            OutputFormat::Text => print_steps(&steps),
+            OutputFormat::Json => records.extend(steps),
+            OutputFormat::Jsonl => {
+                for step in &steps {
+                    println!("{}", serde_json::to_string(step)?);
+                }
+            }
+        }
+        if let Err(e) = result {
+            eprintln!("{}: {}", server.cfg.tasks[index].name, e);
+            code = ExitCode::FAILURE;
+            break;
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+    }
+    Ok(code)
+}
+
+fn main() -> Result<ExitCode> {
     let args = easycfg::args::Args::parse();
+    // only the runs executing tasks are logged, from their first step
+    let log = match args.command {
+        None | Some(Command::Run { .. }) => {
+            RunLog::default_dir().map(|dir| RunLog::new(&dir, KEEP_RUNS))
+        }
+        _ => None,
+    };
+    let buffer = args.command.is_none().then(LogBuffer::default);
+    setup_log(log.as_ref().map(RunLog::writer), buffer.clone())?;
     info!("easycfg directory: {}", args.directory);
-    let server_builder = server::ServerBuilder::new();
+    let os = args.os.or(config::Os::current()).ok_or(anyhow!(
+        "unsupported operating system, select one with --os"
+    ))?;
+    info!("os: {}", os);
+    let facts = config::Facts::gather(os);
+    if let Some(Command::Check { json }) = args.command {
+        return check(args.directory, &facts, json);
+    }
+    let mut server_builder = server::ServerBuilder::new();
     info!("distribution: {}", server_builder.distri);
-    let (cfg, cwd) = config::init(args.directory)?;
-    let server = server_builder.current_dir(cwd).build(cfg)?;
-    let mut tui = Tui::new(server);
+    let (mut cfg, cwd) = config::init(args.directory, &facts)?;
+    cfg.select_profile(args.profile.as_deref(), &facts)?;
+    if let Some(profile) = &cfg.profile {
+        info!("profile: {}", profile);
+    }
+    let filter = config::TagFilter {
+        tags: args.tags,
+        skip: args.skip_tags,
+    };
+    match args.command {
+        Some(Command::Status { json }) => {
+            let mut server = server::Server::new(server_builder.distri, cfg, cwd);
+            return status(&mut server, json);
+        }
+        Some(Command::List) => {
+            cfg.tasks.retain(|t| filter.matches(t));
+            return list(&cfg);
+        }
+        Some(Command::Run { all: true, .. }) => cfg.tasks.retain(|t| filter.matches(t)),
+        _ => {}
+    }
+    if let Some(log) = log {
+        server_builder = server_builder.run_log(log);
+    }
+    let mut server = server_builder
+        .current_dir(cwd)
+        .dry_run(args.dry_run)
+        .build(cfg)?;
+    if let Some(Command::Run {
+        tasks,
+        all,
+        force,
+        output,
+    }) = args.command
+    {
+        return run(&mut server, tasks, all, force, output);
+    }
+    let indices: Vec<_> = (0..server.cfg.tasks.len()).collect();
+    server.ask_sudo(&indices)?;
+    let mut tui = Tui::new(server)
+        .tag_filter(filter)
+        .logs(buffer.unwrap_or_default());
     tui.run()?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -1,60 +1,76 @@
 mod exec;
+pub mod runlog;
+pub mod state;
+pub mod step;
 use anyhow::Result;
+pub use exec::{Change, Drift, DriftKind};
+use runlog::RunLog;
+use state::{Outcome, State, TaskStatus};
+use step::{Step, StepKind, StepStatus};
+use tracing::{info, warn};
 
 use super::config::get_release;
 use super::config::Cfg;
 use super::config::Distri;
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult};
 use std::path::PathBuf;
-use std::process::Output;
 
 // const ROOT_SHELL: [&str; 1] = ["bash"];
 
 #[derive(Default, Debug)]
 pub struct ServerBuilder {
     pub distri: Distri,
-    root: Option<String>,
     current_dir: PathBuf,
+    dry_run: bool,
+    state_file: Option<PathBuf>,
+    log: Option<RunLog>,
 }
 
 impl ServerBuilder {
     pub fn new() -> Self {
         Self {
             distri: get_release(),
-            root: None,
             current_dir: PathBuf::new(),
+            dry_run: false,
+            state_file: State::default_path(),
+            log: None,
         }
     }
     pub fn current_dir(mut self, current_dir: PathBuf) -> Self {
         self.current_dir = current_dir;
         self
     }
-    fn check_root(&mut self, cfg: &Cfg) -> Result<()> {
-        let mut need_root = false;
-        for task in &cfg.tasks {
-            if let Some(pkgs) = &task.pkg {
-                need_root = pkgs.iter().any(|p| p.distri == self.distri);
-                if need_root {
-                    break;
-                }
-            }
-        }
-        if need_root {
-            print!("Enter root password: ");
-            std::io::stdout().flush()?;
-            let mut pass = String::new();
-            std::io::stdin().read_line(&mut pass)?;
-            self.root = Some(pass.trim().to_string());
-        }
-        Ok(())
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+    pub fn state_file(mut self, path: PathBuf) -> Self {
+        self.state_file = Some(path);
+        self
+    }
+    /// Writes the output of every step to this log
+    pub fn run_log(mut self, log: RunLog) -> Self {
+        self.log = Some(log);
+        self
     }
     pub fn build(mut self, cfg: Cfg) -> Result<Server> {
-        self.check_root(&cfg)?;
         if self.current_dir.as_os_str().is_empty() {
             self.current_dir = std::env::current_dir()?;
         }
-        Ok(Server::new(self.distri, cfg, self.current_dir))
+        let revision = state::revision(&self.current_dir);
+        let mut server = Server::new(self.distri, cfg, self.current_dir);
+        server.set_dry_run(self.dry_run);
+        server.log = self.log.take();
+        if let Some(path) = &self.state_file {
+            server.state = State::load(path).unwrap_or_else(|e| {
+                warn!("ignoring the state file {}: {}", path.display(), e);
+                State::default()
+            });
+        }
+        server.state.revision = revision;
+        Ok(server)
     }
 }
 
@@ -62,6 +78,10 @@ impl ServerBuilder {
 pub struct Server {
     pub cfg: Cfg,
     exec: exec::Executer,
+    state: State,
+    /// The [`state::hash`] of each task, until it runs
+    hashes: BTreeMap<usize, String>,
+    log: Option<RunLog>,
 }
 
 impl Server {
@@ -69,11 +89,20 @@ impl Server {
         Self {
             cfg,
             exec: exec::Executer::new(distri, current_dir),
+            state: State::default(),
+            hashes: BTreeMap::new(),
+            log: None,
         }
     }
-    pub fn exec(&mut self, name: &str) -> (Vec<Output>, IoResult<()>) {
-        let task = match self.cfg.tasks.iter().find(|t| t.name == name) {
-            Some(task) => task,
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.exec.dry_run = dry_run;
+    }
+    pub fn dry_run(&self) -> bool {
+        self.exec.dry_run
+    }
+    pub fn exec(&mut self, name: &str) -> (Vec<Step>, IoResult<()>) {
+        let index = match self.cfg.tasks.iter().position(|t| t.name == name) {
+            Some(index) => index,
             None => {
                 return (
                     Vec::new(),
@@ -81,9 +110,18 @@ impl Server {
                 )
             }
         };
-        self.exec.exec(task)
+        self.run(index)
     }
-    pub fn exec_by_index(&mut self, index: usize) -> (Vec<Output>, IoResult<()>) {
+    pub fn exec_by_index(&mut self, index: usize) -> (Vec<Step>, IoResult<()>) {
+        if index >= self.cfg.tasks.len() {
+            return (
+                Vec::new(),
+                Err(IoError::new(IoErrorKind::NotFound, "Index error")),
+            );
+        }
+        self.run(index)
+    }
+    pub fn undo_by_index(&mut self, index: usize) -> (Vec<Step>, IoResult<()>) {
         if index >= self.cfg.tasks.len() {
             return (
                 Vec::new(),
@@ -91,12 +129,33 @@ impl Server {
             );
         }
         let task = &self.cfg.tasks[index];
-        self.exec.exec(task)
+        let (outputs, result) = self.exec.undo(task);
+        write_log(&self.log, &outputs);
+        if result.is_ok() && !self.exec.dry_run {
+            self.state.tasks.remove(&task.name);
+            self.save_state();
+        }
+        (outputs, result)
+    }
+    /// The tasks not applied with their current definition, in order.
+    pub fn outdated(&mut self) -> Vec<usize> {
+        (0..self.cfg.tasks.len())
+            .filter(|i| self.status(*i) != TaskStatus::Applied)
+            .collect()
     }
-    pub fn exec_all(&mut self) -> (Vec<Output>, IoResult<()>) {
+    pub fn exec_all(&mut self) -> (Vec<Step>, IoResult<()>) {
         let mut outputs = Vec::new();
-        for task in &self.cfg.tasks {
-            let (output, result) = self.exec.exec(task);
+        let outdated = self.outdated();
+        for index in 0..self.cfg.tasks.len() {
+            let task = &self.cfg.tasks[index];
+            if !outdated.contains(&index) {
+                let mut step = Step::new(&task.name, StepKind::Task, task.name.clone());
+                step.stdout = b"unchanged since the last run".to_vec();
+                step.status = StepStatus::Skipped;
+                outputs.push(step);
+                continue;
+            }
+            let (output, result) = self.run(index);
             outputs.extend(output);
             if result.is_err() {
                 return (outputs, result);
@@ -104,4 +163,133 @@ impl Server {
         }
         (outputs, Ok(()))
     }
+    /// Compares every task with the machine without changing anything.
+    pub fn drift(&mut self) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+        for task in &self.cfg.tasks {
+            drifts.extend(self.exec.drift(task));
+        }
+        drifts
+    }
+    /// Compares the task at `index` with the machine.
+    pub fn drift_by_index(&mut self, index: usize) -> Vec<Drift> {
+        self.exec.drift(&self.cfg.tasks[index])
+    }
+    /// The changes running the task at `index` makes, given how it drifts.
+    pub fn plan(&self, index: usize, drifts: &[Drift]) -> Vec<Change> {
+        self.exec.plan(&self.cfg.tasks[index], drifts)
+    }
+    /// The changes undoing the task at `index` makes.
+    pub fn undo_plan(&self, index: usize) -> IoResult<Vec<Change>> {
+        self.exec.undo_plan(&self.cfg.tasks[index])
+    }
+    /// The distribution the packages are installed for.
+    pub fn distri(&self) -> Distri {
+        self.exec.distri()
+    }
+    /// Asks for the password of the user when one of these tasks runs the
+    /// package manager through `sudo`, which a dry run never does.
+    pub fn ask_sudo(&mut self, indices: &[usize]) -> Result<()> {
+        if self.exec.dry_run || self.exec.root.is_some() || is_root() {
+            return Ok(());
+        }
+        let distri = self.exec.distri();
+        let packages = indices.iter().any(|i| {
+            let pkgs = self.cfg.tasks[*i].pkg.iter().flatten();
+            pkgs.filter(|p| p.distri == distri)
+                .any(|p| p.install.iter().chain(&p.remove).flatten().next().is_some())
+        });
+        if packages {
+            self.exec.root = Some(read_password("Password for sudo: ")?);
+        }
+        Ok(())
+    }
+    /// How the task at `index` relates to its last run.
+    pub fn status(&mut self, index: usize) -> TaskStatus {
+        let task = &self.cfg.tasks[index];
+        let hash = self
+            .hashes
+            .entry(index)
+            .or_insert_with(|| state::hash(task));
+        self.state.status_hashed(task, hash)
+    }
+    /// Runs a task and records its outcome, unless in dry-run.
+    fn run(&mut self, index: usize) -> (Vec<Step>, IoResult<()>) {
+        let task = &self.cfg.tasks[index];
+        let (outputs, result) = self.exec.exec(task);
+        write_log(&self.log, &outputs);
+        self.hashes.remove(&index);
+        if !self.exec.dry_run {
+            let outcome = match result {
+                Ok(()) => Outcome::Success,
+                Err(_) => Outcome::Failure,
+            };
+            let artifacts = self.exec.artifacts(task);
+            self.state.record(task, outcome, artifacts);
+            self.save_state();
+        }
+        (outputs, result)
+    }
+    fn save_state(&self) {
+        if let Err(e) = self.state.save() {
+            warn!("could not save the state: {}", e);
+        }
+    }
+}
+
+fn write_log(log: &Option<RunLog>, steps: &[Step]) {
+    let Some(log) = log else {
+        return;
+    };
+    let created = log.created();
+    match log.write_steps(steps) {
+        Err(e) => warn!(
+            "could not write the run log {}: {}",
+            log.path().display(),
+            e
+        ),
+        Ok(()) if !created => info!("run log: {}", log.path().display()),
+        Ok(()) => {}
+    }
+}
+
+/// Whether easycfg already runs as root, needing no password for `sudo`.
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Prompts for a password on the terminal without echoing it.
+fn read_password(prompt: &str) -> Result<String> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("sudo needs a password, but stdin is not a terminal");
+    }
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let mut password = String::new();
+    let result = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(IoError::new(IoErrorKind::Interrupted, "interrupted"))
+            }
+            KeyCode::Char(c) => password.push(c),
+            KeyCode::Backspace => {
+                password.pop();
+            }
+            _ => {}
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    println!();
+    result?;
+    Ok(password)
 }
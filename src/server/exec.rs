@@ -1,14 +1,31 @@
+mod drift;
 mod pkg;
-use crate::config::Task;
+mod plan;
+mod stow;
+use super::step::{Step, StepKind, StepStatus};
+use crate::config::{Cmd, LinkMode, Stow, Symlink, Task, Template};
+pub use drift::{Drift, DriftKind};
 use pkg::DistriOpt;
+pub use plan::Change;
+use sha2::{Digest, Sha256};
 use std::io::{ErrorKind as IoErrorKind, Result as IoResult};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Output;
+use std::time::Instant;
+
+/// Appended to a file or directory replaced by easycfg, to restore it on undo,
+/// followed by a number when an older backup exists
+pub const BACKUP_SUFFIX: &str = ".easycfg.bak";
+
 #[derive(Default, Debug)]
 pub struct Executer {
     distri: DistriOpt,
     current_dir: PathBuf,
+    /// Report the steps without changing anything
+    pub dry_run: bool,
+    /// The password of the user given to `sudo` to run the package manager
+    pub root: Option<String>,
 }
 
 impl Executer {
@@ -16,8 +33,13 @@ impl Executer {
         Self {
             distri: distri.into(),
             current_dir,
+            dry_run: false,
+            root: None,
         }
     }
+    pub fn distri(&self) -> crate::config::Distri {
+        self.distri.distri
+    }
     fn exec_cmd(&self, cmd: &str, current_dir: PathBuf) -> IoResult<Output> {
         std::process::Command::new("sh")
             .current_dir(current_dir)
@@ -43,9 +65,7 @@ impl Executer {
                 break Ok(());
             };
             match err.kind() {
-                IoErrorKind::AlreadyExists => {
-                    std::fs::remove_file(&dst).or_else(|_| std::fs::remove_dir_all(&dst))?;
-                }
+                IoErrorKind::AlreadyExists => plan::clear(dst.as_ref())?,
                 IoErrorKind::NotFound => {
                     // let a=IoErrorKind::IsADirectory;
                     let _ = dst
@@ -58,88 +78,529 @@ impl Executer {
             };
         }
     }
-    fn pkg_apply(&mut self, pkg: &crate::config::Package) -> IoResult<()> {
-        todo!()
+    /// Copies `src` to `dst` recursively, keeping permissions and skipping the
+    /// files whose content hash did not change. Returns the number of files copied.
+    fn copy(&mut self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> IoResult<usize> {
+        let (src, dst) = (src.as_ref(), dst.as_ref());
+        let meta = std::fs::symlink_metadata(dst).ok();
+        if src.is_dir() {
+            if !self.dry_run {
+                if meta.as_ref().is_some_and(|m| !m.is_dir()) {
+                    plan::clear(dst)?;
+                }
+                std::fs::create_dir_all(dst)?;
+            }
+            let mut copied = 0;
+            for entry in std::fs::read_dir(src)? {
+                let entry = entry?;
+                copied += self.copy(entry.path(), dst.join(entry.file_name()))?;
+            }
+            return Ok(copied);
+        }
+        let unchanged = match &meta {
+            Some(meta) if meta.is_file() => hash_file(src)? == hash_file(dst)?,
+            _ => false,
+        };
+        if self.dry_run {
+            return Ok(if unchanged { 0 } else { 1 });
+        }
+        if unchanged {
+            std::fs::set_permissions(dst, std::fs::metadata(src)?.permissions())?;
+            return Ok(0);
+        }
+        match &meta {
+            Some(_) => plan::clear(dst)?,
+            None => {
+                if let Some(parent) = dst.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+        }
+        std::fs::copy(src, dst)?;
+        Ok(1)
+    }
+    fn hardlink(&mut self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> IoResult<()> {
+        use std::os::unix::fs::MetadataExt;
+        let (src, dst) = (src.as_ref(), dst.as_ref());
+        let meta = std::fs::metadata(src)?;
+        if !meta.is_file() {
+            return Err(std::io::Error::new(
+                IoErrorKind::InvalidInput,
+                format!("{} is not a regular file", src.display()),
+            ));
+        }
+        match std::fs::symlink_metadata(dst) {
+            Ok(old) if old.dev() == meta.dev() && old.ino() == meta.ino() => return Ok(()),
+            Ok(_) => plan::clear(dst)?,
+            Err(_) => {
+                if let Some(parent) = dst.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+        }
+        std::fs::hard_link(src, dst)
     }
-
-    pub fn exec(&mut self, task: &Task) -> (Vec<Output>, IoResult<()>) {
-        let mut outputs = Vec::new();
-        if let Some(symlinks) = &task.slink {
-            for (src, dst) in symlinks {
-                let mut output = Output {
-                    stdout: Vec::new(),
-                    stderr: Vec::new(),
-                    status: std::process::ExitStatus::default(),
+    /// Writes a rendered template, describing the change made to the destination.
+    fn template(&mut self, tpl: &Template) -> IoResult<String> {
+        if let Some(error) = &tpl.error {
+            return Err(std::io::Error::other(error.clone()));
+        }
+        let dst = Path::new(&tpl.dst);
+        let meta = std::fs::symlink_metadata(dst).ok();
+        let old = match &meta {
+            Some(meta) if meta.is_file() => Some(std::fs::read_to_string(dst)?),
+            _ => None,
+        };
+        if old.as_deref() == Some(tpl.content.as_str()) {
+            return Ok("unchanged".to_string());
+        }
+        if !self.dry_run {
+            if meta.is_some() {
+                plan::clear(dst)?;
+            }
+            if let Some(parent) = dst.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(dst, &tpl.content)?;
+        }
+        Ok(match old {
+            None => "created".to_string(),
+            Some(old) => format!(
+                "updated\n{}",
+                similar::TextDiff::from_lines(&old, &tpl.content)
+                    .unified_diff()
+                    .header(&tpl.dst, &tpl.dst)
+            ),
+        })
+    }
+    /// Mirrors a directory tree into its destination. Nothing is changed when
+    /// any destination is in use, the error then lists every conflict.
+    fn stow(&mut self, stow: &Stow) -> IoResult<String> {
+        let plan = stow::Plan::new(stow)?;
+        if !plan.conflicts.is_empty() {
+            let conflicts: Vec<_> = plan
+                .conflicts
+                .iter()
+                .map(|(dst, reason)| format!("{}: {}", dst.display(), reason))
+                .collect();
+            return Err(std::io::Error::new(
+                IoErrorKind::AlreadyExists,
+                format!("conflicts:\n{}", conflicts.join("\n")),
+            ));
+        }
+        if !self.dry_run {
+            for dir in &plan.dirs {
+                std::fs::create_dir_all(dir)?;
+            }
+            for (src, dst) in &plan.links {
+                self.symlink(src, dst)?;
+            }
+        }
+        Ok(match plan.links.len() {
+            0 => "unchanged".to_string(),
+            n => format!("{} link(s) created", n),
+        })
+    }
+    /// Runs one step, recording its duration and the change it made or its error.
+    fn step(
+        &mut self,
+        task: &Task,
+        kind: StepKind,
+        command: String,
+        steps: &mut Vec<Step>,
+        run: impl FnOnce(&mut Self) -> IoResult<String>,
+    ) -> IoResult<()> {
+        let start = Instant::now();
+        let result = run(self);
+        let mut step = Step::new(&task.name, kind, command);
+        step.duration = start.elapsed();
+        let result = match result {
+            Ok(change) => {
+                step.stdout = change.into_bytes();
+                step.status = match self.dry_run {
+                    true => StepStatus::DryRun,
+                    false => StepStatus::Success,
                 };
-                match self.symlink(src, dst) {
-                    Ok(_) => {
-                        output.stdout = format!("[symlink] {} -> {}", src, dst).into_bytes();
-                    }
-                    Err(e) => {
-                        output.stderr = format!("[symlink] {} -> {}: {}", src, dst, e).into_bytes();
-                        outputs.push(output);
-                        return (outputs, Err(e));
+                Ok(())
+            }
+            Err(e) => {
+                step.stderr = e.to_string().into_bytes();
+                step.status = StepStatus::Failure;
+                Err(e)
+            }
+        };
+        steps.push(step);
+        result
+    }
+    /// Runs one step spawning a process, failing when it exits unsuccessfully.
+    fn process_step(
+        &mut self,
+        task: &Task,
+        kind: StepKind,
+        command: String,
+        steps: &mut Vec<Step>,
+        run: impl FnOnce(&Self) -> IoResult<Output>,
+    ) -> IoResult<()> {
+        let mut step = Step::new(&task.name, kind, command);
+        if self.dry_run {
+            step.status = StepStatus::DryRun;
+            steps.push(step);
+            return Ok(());
+        }
+        let begin = Instant::now();
+        let result = run(self);
+        step.duration = begin.elapsed();
+        let result = match result {
+            Ok(output) => {
+                step.stdout = output.stdout;
+                step.stderr = output.stderr;
+                step.exit_code = output.status.code();
+                match output.status.success() {
+                    true => Ok(()),
+                    false => Err(std::io::Error::other(format!(
+                        "{} failed with {}",
+                        step.command, output.status
+                    ))),
+                }
+            }
+            Err(e) => {
+                step.stderr = e.to_string().into_bytes();
+                Err(e)
+            }
+        };
+        if result.is_err() {
+            step.status = StepStatus::Failure;
+        }
+        steps.push(step);
+        result
+    }
+    /// Runs commands in the directory of the task, stopping at the first error.
+    fn run_commands<'a>(
+        &mut self,
+        task: &Task,
+        commands: impl Iterator<Item = &'a Cmd>,
+        steps: &mut Vec<Step>,
+    ) -> IoResult<()> {
+        let dir = self
+            .current_dir
+            .join(task.path.as_deref().unwrap_or(task.name.as_str()));
+        for cmd in commands {
+            self.process_step(task, StepKind::Exec, cmd.run.clone(), steps, |exec| {
+                exec.exec_cmd(&cmd.run, dir.clone())
+            })?;
+        }
+        Ok(())
+    }
+    /// Applies the revert planned for a link or template on undo, removing
+    /// it then restoring its latest backup.
+    fn restore(&mut self, change: Change) -> IoResult<String> {
+        let (path, backup) = match change {
+            Change::Remove(path, backup) => (path, backup),
+            Change::Keep(_, reason) => return Ok(format!("kept, {}", reason)),
+            change => return Ok(change.to_string()),
+        };
+        let exists = std::fs::symlink_metadata(&path).is_ok();
+        if !self.dry_run {
+            if exists {
+                std::fs::remove_file(&path)?;
+            }
+            if let Some(backup) = &backup {
+                std::fs::rename(backup, &path)?;
+            }
+        }
+        Ok(match (exists, backup.is_some()) {
+            (true, true) => "removed, backup restored",
+            (true, false) => "removed",
+            (false, true) => "backup restored",
+            (false, false) => "not present",
+        }
+        .to_string())
+    }
+    /// Removes the copied files still identical to their source, and the
+    /// directories left empty. Returns the number of files removed and kept.
+    /// Removes what a copy left unmodified, restoring the latest backup of
+    /// each removed path, and lists it in `changes`. Whether nothing is left
+    /// at `dst` afterwards.
+    fn uncopy(
+        &self,
+        src: &Path,
+        dst: &Path,
+        dry_run: bool,
+        changes: &mut Vec<Change>,
+    ) -> IoResult<bool> {
+        let Ok(meta) = std::fs::symlink_metadata(dst) else {
+            return Ok(true);
+        };
+        if src.is_dir() && meta.is_dir() {
+            let mut left = std::fs::read_dir(dst)?.count();
+            for entry in std::fs::read_dir(src)? {
+                let entry = entry?;
+                let child = dst.join(entry.file_name());
+                let present = std::fs::symlink_metadata(&child).is_ok();
+                if self.uncopy(&entry.path(), &child, dry_run, changes)? && present {
+                    left -= 1;
+                }
+            }
+            if left > 0 {
+                return Ok(false);
+            }
+            if !dry_run {
+                std::fs::remove_dir(dst)?;
+            }
+            let Some(backup) = latest_backup(dst) else {
+                return Ok(true);
+            };
+            if !dry_run {
+                std::fs::rename(&backup, dst)?;
+            }
+            changes.push(Change::Remove(
+                dst.to_string_lossy().to_string(),
+                Some(backup),
+            ));
+            return Ok(false);
+        }
+        let path = dst.to_string_lossy().to_string();
+        if !src.is_file() || !meta.is_file() || hash_file(src)? != hash_file(dst)? {
+            changes.push(Change::Keep(path, "modified since it was copied"));
+            return Ok(false);
+        }
+        let backup = latest_backup(dst);
+        if !dry_run {
+            std::fs::remove_file(dst)?;
+            if let Some(backup) = &backup {
+                std::fs::rename(backup, dst)?;
+            }
+        }
+        let gone = backup.is_none();
+        changes.push(Change::Remove(path, backup));
+        Ok(gone)
+    }
+    /// Removes the links of a mirrored tree and the directories left empty.
+    fn unstow(&mut self, stow: &Stow) -> IoResult<String> {
+        let links = stow::installed(stow, &self.current_dir)?;
+        if !self.dry_run {
+            for link in &links {
+                std::fs::remove_file(link)?;
+                let mut dir = link.parent();
+                while let Some(parent) = dir.filter(|d| *d != Path::new(&stow.dst)) {
+                    if std::fs::read_dir(parent)?.next().is_some() {
+                        break;
                     }
+                    std::fs::remove_dir(parent)?;
+                    dir = parent.parent();
                 }
-                // todo!()
-                // status: std::process::ExitStatus::default(),
-                outputs.push(output);
-            }
-        }
-        if let Some(commands) = &task.commands {
-            for cmd in commands {
-                let start = format!("[exec] {}\n", cmd).into_bytes();
-                let mut output = Output {
-                    stdout: start.clone(),
-                    stderr: Vec::new(),
-                    status: std::process::ExitStatus::default(),
-                };
-                match self.exec_cmd(
-                    cmd,
-                    self.current_dir
-                        .join(task.path.as_deref().unwrap_or(task.name.as_str())),
-                ) {
-                    Ok(exec_output) => {
-                        output.stdout.extend(exec_output.stdout.into_iter());
-                        if !exec_output.stderr.is_empty() {
-                            output.stderr.extend(start);
-                            output.stderr.extend(exec_output.stderr.into_iter());
-                        }
-                        outputs.push(output);
+            }
+        }
+        Ok(format!("{} link(s) removed", links.len()))
+    }
+    /// Runs a package manager command on `names`, through `sudo` when the
+    /// password of the user was given. `-k` makes `sudo` read it even with
+    /// cached credentials, so it never reaches the package manager.
+    fn pkg_command(&self, cmd: &pkg::Command, names: &[String]) -> IoResult<Output> {
+        use std::io::Write;
+        let mut command = match &self.root {
+            Some(_) => {
+                let mut command = std::process::Command::new("sudo");
+                command.args(["-k", "-S", "-p", ""]).arg(&cmd.exec);
+                command
+            }
+            None => std::process::Command::new(&cmd.exec),
+        };
+        let mut child = command
+            .args(&cmd.args)
+            .args(names)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Some(password) = &self.root {
+                writeln!(stdin, "{}", password)?;
+            }
+            if let Some(input) = &cmd.stdin {
+                stdin.write_all(input.as_bytes())?;
+            }
+        }
+        child.wait_with_output()
+    }
+    /// Runs a package manager command as a step, unless the distribution has none.
+    fn pkg_step(
+        &mut self,
+        task: &Task,
+        kind: StepKind,
+        cmd: &pkg::Command,
+        names: &[String],
+        steps: &mut Vec<Step>,
+    ) -> IoResult<()> {
+        if cmd.exec.is_empty() || names.is_empty() {
+            return Ok(());
+        }
+        let line = std::iter::once(&cmd.exec)
+            .chain(&cmd.args)
+            .chain(names)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.process_step(task, kind, line, steps, |exec| exec.pkg_command(cmd, names))
+    }
+
+    pub fn exec(&mut self, task: &Task) -> (Vec<Step>, IoResult<()>) {
+        let mut steps = Vec::new();
+        let result = self.exec_steps(task, &mut steps);
+        (steps, result)
+    }
+    fn exec_steps(&mut self, task: &Task, steps: &mut Vec<Step>) -> IoResult<()> {
+        let (distri, install) = (self.distri.distri, self.distri.install.clone());
+        let pkgs = task.pkg.iter().flatten().filter(|p| p.distri == distri);
+        let names: Vec<_> = pkgs
+            .flat_map(|p| p.install.iter().flatten().cloned())
+            .collect();
+        self.pkg_step(task, StepKind::Install, &install, &names, steps)?;
+        for Symlink { src, dst, mode, .. } in task.slink.iter().flatten() {
+            let command = format!("{} -> {}", src, dst);
+            self.step(task, (*mode).into(), command, steps, |exec| match mode {
+                _ if exec.dry_run && *mode != LinkMode::Copy => Ok(String::new()),
+                LinkMode::Symlink => exec.symlink(src, dst).map(|_| String::new()),
+                LinkMode::Hardlink => exec.hardlink(src, dst).map(|_| String::new()),
+                LinkMode::Copy => exec.copy(src, dst).map(|n| match n {
+                    0 => "unchanged".to_string(),
+                    n => format!("{} file(s) copied", n),
+                }),
+            })?;
+        }
+        for tpl in task.templates.iter().flatten() {
+            let command = format!("{} -> {}", tpl.src, tpl.dst);
+            self.step(task, StepKind::Template, command, steps, |exec| {
+                exec.template(tpl)
+            })?;
+        }
+        for stow in task.stow.iter().flatten() {
+            let command = format!("{} -> {}", stow.src, stow.dst);
+            self.step(task, StepKind::Stow, command, steps, |exec| exec.stow(stow))?;
+        }
+        self.run_commands(task, task.commands.iter().flatten(), steps)
+    }
+
+    /// The files and links created by a task.
+    pub fn artifacts(&self, task: &Task) -> Vec<String> {
+        let links = task.slink.iter().flatten().map(|l| l.dst.clone());
+        let templates = task.templates.iter().flatten().map(|t| t.dst.clone());
+        let stowed = task
+            .stow
+            .iter()
+            .flatten()
+            .flat_map(|s| stow::installed(s, &self.current_dir).unwrap_or_default())
+            .map(|p| p.to_string_lossy().to_string());
+        links.chain(templates).chain(stowed).collect()
+    }
+
+    /// Reverts a task: runs its uninstall commands, removes the links, copies,
+    /// templates and mirrored trees it left unmodified, restoring backups, then
+    /// removes the packages listed in its `remove`.
+    pub fn undo(&mut self, task: &Task) -> (Vec<Step>, IoResult<()>) {
+        let mut steps = Vec::new();
+        let result = self.undo_steps(task, &mut steps);
+        (steps, result)
+    }
+    fn undo_steps(&mut self, task: &Task, steps: &mut Vec<Step>) -> IoResult<()> {
+        self.run_commands(task, task.uninstall_commands.iter().flatten(), steps)?;
+        for link in task.slink.iter().flatten() {
+            let (src, dst) = (Path::new(&link.src), Path::new(&link.dst));
+            let kind = match link.mode {
+                LinkMode::Copy => StepKind::Remove,
+                _ => StepKind::Unlink,
+            };
+            self.step(task, kind, link.dst.clone(), steps, |exec| {
+                match link.mode {
+                    LinkMode::Symlink | LinkMode::Hardlink => {
+                        let change = exec.revert_link(link)?;
+                        exec.restore(change)
                     }
-                    Err(e) => {
-                        output.stderr.extend(start);
-                        outputs.push(output);
-                        return (outputs, Err(e));
+                    LinkMode::Copy => {
+                        let mut changes = Vec::new();
+                        exec.uncopy(src, dst, exec.dry_run, &mut changes)?;
+                        Ok(uncopied(&changes))
                     }
                 }
+            })?;
+        }
+        for tpl in task.templates.iter().flatten() {
+            self.step(task, StepKind::Remove, tpl.dst.clone(), steps, |exec| {
+                let change = exec.revert_template(tpl)?;
+                exec.restore(change)
+            })?;
+        }
+        for stow in task.stow.iter().flatten() {
+            let command = format!("{} -> {}", stow.src, stow.dst);
+            self.step(task, StepKind::Unstow, command, steps, |exec| {
+                exec.unstow(stow)
+            })?;
+        }
+        let (distri, remove) = (self.distri.distri, self.distri.remove.clone());
+        let names = removed_packages(task, distri);
+        self.pkg_step(task, StepKind::Uninstall, &remove, &names, steps)
+    }
+}
+
+/// How many files undoing a copy removed, restored from their backup and
+/// kept.
+fn uncopied(changes: &[Change]) -> String {
+    let (mut removed, mut restored, mut kept) = (0, 0, 0);
+    for change in changes {
+        match change {
+            Change::Remove(_, backup) => {
+                removed += 1;
+                restored += backup.is_some() as usize;
             }
+            _ => kept += 1,
         }
-        // if let Some(pkgs) = &task.pkg {
-        //     for pkg in pkgs {
-        //         if pkg.distri != self.distri.distri {
-        //             continue;
-        //         }
-        //         let mut output = Output {
-        //             stdout: Vec::new(),
-        //             stderr: Vec::new(),
-        //             status: std::process::ExitStatus::default(),
-        //         };
-        //         match self.pkg_apply(pkg) {
-        //             Ok(_) => {
-        //                 output.stdout = format!("[pkg] {:?}\n", pkg).into_bytes();
-        //             }
-        //             Err(e) => {
-        //                 output.stderr = format!("[pkg] {:?}: {}\n", pkg, e).into_bytes();
-        //                 outputs.push(output);
-        //                 return (outputs, Err(e));
-        //             }
-        //         }
-        //         outputs.push(output);
-        //     }
-        // }
-        (outputs, Ok(()))
     }
+    let mut message = format!("{} file(s) removed", removed);
+    if restored > 0 {
+        message += &format!(", {} backup(s) restored", restored);
+    }
+    if kept > 0 {
+        message += &format!(", {} modified file(s) kept", kept);
+    }
+    message
+}
+
+/// The packages removed when undoing `task`, the ones its `remove` lists
+/// for the distribution rather than everything it installed.
+fn removed_packages(task: &Task, distri: crate::config::Distri) -> Vec<String> {
+    let pkgs = task.pkg.iter().flatten().filter(|p| p.distri == distri);
+    pkgs.flat_map(|p| p.remove.iter().flatten().cloned())
+        .collect()
+}
+
+/// The first free backup of `path`: `<path>.easycfg.bak`, then
+/// `<path>.easycfg.bak.1` and so on, so an older backup is never overwritten.
+fn backup_path(path: &Path) -> PathBuf {
+    (0..)
+        .map(|n| numbered_backup(path, n))
+        .find(|backup| std::fs::symlink_metadata(backup).is_err())
+        .unwrap_or_default()
+}
+
+/// The most recent backup of `path`, the one restored on undo.
+fn latest_backup(path: &Path) -> Option<PathBuf> {
+    (0..)
+        .map(|n| numbered_backup(path, n))
+        .take_while(|backup| std::fs::symlink_metadata(backup).is_ok())
+        .last()
+}
+
+fn numbered_backup(path: &Path, n: usize) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(BACKUP_SUFFIX);
+    if n > 0 {
+        backup.push(format!(".{}", n));
+    }
+    backup.into()
+}
+
+fn hash_file(path: impl AsRef<Path>) -> IoResult<Vec<u8>> {
+    Ok(Sha256::digest(std::fs::read(path)?).to_vec())
 }
 
 #[cfg(test)]
@@ -0,0 +1,186 @@
+//! Differences between what the tasks describe and the machine.
+use super::{stow, Executer};
+use crate::config::{LinkMode, Symlink, Task};
+use serde::Serialize;
+use std::io::Result as IoResult;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum DriftKind {
+    /// A link, copy or template destination does not exist
+    Missing,
+    /// A link points elsewhere or was replaced
+    WrongTarget,
+    /// A copied or templated file differs from its source
+    Modified,
+    NotInstalled,
+    /// Could not be checked, a source missing for instance
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    pub kind: DriftKind,
+    pub task: String,
+    /// The destination or package of the task which drifted
+    pub item: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: [{}] {}", self.task, self.kind, self.message)
+    }
+}
+
+impl Executer {
+    /// Compares a task with the machine without changing anything, what
+    /// cannot be checked being reported as an error of its item.
+    pub fn drift(&mut self, task: &Task) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+        let mut push = |kind, item: &str, message| {
+            drifts.push(Drift {
+                kind,
+                task: task.name.clone(),
+                item: item.to_string(),
+                message,
+            })
+        };
+        for link in task.slink.iter().flatten() {
+            match self.link_drift(link) {
+                Ok(Some((kind, message))) => push(kind, &link.dst, message),
+                Ok(None) => {}
+                Err(e) => push(
+                    DriftKind::Error,
+                    &link.dst,
+                    format!("{} -> {}: {}", link.src, link.dst, e),
+                ),
+            }
+        }
+        for tpl in task.templates.iter().flatten() {
+            if let Some(error) = &tpl.error {
+                push(DriftKind::Error, &tpl.dst, error.clone());
+                continue;
+            }
+            match std::fs::read_to_string(&tpl.dst) {
+                Err(_) => push(
+                    DriftKind::Missing,
+                    &tpl.dst,
+                    format!("{} does not exist", tpl.dst),
+                ),
+                Ok(content) if content != tpl.content => push(
+                    DriftKind::Modified,
+                    &tpl.dst,
+                    format!("{} differs from the template {}", tpl.dst, tpl.src),
+                ),
+                Ok(_) => {}
+            }
+        }
+        for tree in task.stow.iter().flatten() {
+            let plan = match stow::Plan::new(tree) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    push(
+                        DriftKind::Error,
+                        &tree.dst,
+                        format!("{} -> {}: {}", tree.src, tree.dst, e),
+                    );
+                    continue;
+                }
+            };
+            for (_, dst) in &plan.links {
+                push(
+                    DriftKind::Missing,
+                    &tree.dst,
+                    format!("{} is not linked", dst.display()),
+                );
+            }
+            for (dst, reason) in &plan.conflicts {
+                push(
+                    DriftKind::WrongTarget,
+                    &tree.dst,
+                    format!("{} {}", dst.display(), reason),
+                );
+            }
+        }
+        let query = &self.distri.query;
+        let pkgs = task
+            .pkg
+            .iter()
+            .flatten()
+            .filter(|p| p.distri == self.distri.distri);
+        for name in pkgs.flat_map(|p| p.install.iter().flatten()) {
+            if query.exec.is_empty() {
+                break;
+            }
+            let installed = std::process::Command::new(&query.exec)
+                .args(&query.args)
+                .arg(name)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .map(|status| status.success());
+            match installed {
+                Ok(true) => {}
+                Ok(false) => push(
+                    DriftKind::NotInstalled,
+                    name,
+                    format!("package {} is not installed", name),
+                ),
+                Err(e) => push(
+                    DriftKind::Error,
+                    name,
+                    format!("package {}: {}: {}", name, query.exec, e),
+                ),
+            }
+        }
+        drifts
+    }
+    /// How a link, hard link or copy drifted, if it did.
+    fn link_drift(&mut self, link: &Symlink) -> IoResult<Option<(DriftKind, String)>> {
+        let dst = Path::new(&link.dst);
+        let Ok(meta) = std::fs::symlink_metadata(dst) else {
+            let message = format!("{} does not exist", link.dst);
+            return Ok(Some((DriftKind::Missing, message)));
+        };
+        let drift = match link.mode {
+            LinkMode::Symlink if !meta.is_symlink() => Some((
+                DriftKind::WrongTarget,
+                format!("{} is not a symlink", link.dst),
+            )),
+            LinkMode::Symlink => {
+                let target = std::fs::read_link(dst)?;
+                (target != Path::new(&link.src)).then(|| {
+                    (
+                        DriftKind::WrongTarget,
+                        format!("{} links to {}", link.dst, target.display()),
+                    )
+                })
+            }
+            LinkMode::Hardlink => {
+                use std::os::unix::fs::MetadataExt;
+                let src = std::fs::metadata(&link.src)?;
+                ((src.dev(), src.ino()) != (meta.dev(), meta.ino())).then(|| {
+                    (
+                        DriftKind::WrongTarget,
+                        format!("{} is not a hard link to {}", link.dst, link.src),
+                    )
+                })
+            }
+            LinkMode::Copy => {
+                let dry_run = std::mem::replace(&mut self.dry_run, true);
+                let changed = self.copy(&link.src, dst);
+                self.dry_run = dry_run;
+                (changed? > 0).then(|| {
+                    (
+                        DriftKind::Modified,
+                        format!("{} differs from {}", link.dst, link.src),
+                    )
+                })
+            }
+        };
+        Ok(drift)
+    }
+}
@@ -1,7 +1,7 @@
 use crate::config::Distri;
 
-#[derive(Default, Debug)]
-struct Command {
+#[derive(Default, Debug, Clone)]
+pub struct Command {
     pub exec: String,
     pub args: Vec<String>,
     pub stdin: Option<String>,
@@ -11,6 +11,9 @@ struct Command {
 pub struct DistriOpt {
     pub distri: Distri,
     pub install: Command,
+    pub remove: Command,
+    /// Succeeds when the package is installed
+    pub query: Command,
 }
 
 impl From<Distri> for DistriOpt {
@@ -23,22 +26,52 @@ impl From<Distri> for DistriOpt {
                     args: vec![],
                     stdin: None,
                 },
+                remove: Command {
+                    exec: "".to_string(),
+                    args: vec![],
+                    stdin: None,
+                },
+                query: Command {
+                    exec: "".to_string(),
+                    args: vec![],
+                    stdin: None,
+                },
             },
             Distri::Arch => DistriOpt {
                 distri,
                 install: Command {
                     exec: "pacman".to_string(),
-                    args: vec!["-S".to_string()],
+                    args: vec!["-S".to_string(), "--noconfirm".to_string()],
+                    stdin: Some("\n".to_string()),
+                },
+                remove: Command {
+                    exec: "pacman".to_string(),
+                    args: vec!["-R".to_string(), "--noconfirm".to_string()],
                     stdin: Some("\n".to_string()),
                 },
+                query: Command {
+                    exec: "pacman".to_string(),
+                    args: vec!["-Q".to_string()],
+                    stdin: None,
+                },
             },
             Distri::OpenSUSE => DistriOpt {
                 distri,
                 install: Command {
                     exec: "zypper".to_string(),
-                    args: vec!["install".to_string()],
+                    args: vec!["install".to_string(), "-y".to_string()],
                     stdin: Some("\n".to_string()),
                 },
+                remove: Command {
+                    exec: "zypper".to_string(),
+                    args: vec!["remove".to_string(), "-y".to_string()],
+                    stdin: Some("\n".to_string()),
+                },
+                query: Command {
+                    exec: "rpm".to_string(),
+                    args: vec!["-q".to_string()],
+                    stdin: None,
+                },
             },
         }
     }
@@ -0,0 +1,201 @@
+//! What running or undoing a task changes on the machine, listed before it
+//! is confirmed and decided by the same functions the run uses.
+use super::{latest_backup, removed_packages, stow, Drift, DriftKind, Executer};
+use crate::config::{LinkMode, Symlink, Task, Template};
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Create(String),
+    Install(String),
+    Overwrite(String),
+    /// A symlink replaced by the new link or file
+    Replace(String),
+    /// What is in the way moved to its backup
+    Backup(String, PathBuf),
+    Conflict(String),
+    Run(usize),
+    RunUninstall(usize),
+    /// Removed, restoring its latest backup if any
+    Remove(String, Option<PathBuf>),
+    /// Left in place on undo, for this reason
+    Keep(String, &'static str),
+    /// The links of a mirrored tree removed
+    Unstow(String, usize),
+    Uninstall(String),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Change::Create(path) => write!(f, "create {}", path),
+            Change::Install(name) => write!(f, "install the package {}", name),
+            Change::Overwrite(path) => write!(f, "overwrite {}", path),
+            Change::Replace(path) => write!(f, "replace the link {}", path),
+            Change::Backup(path, backup) => write!(f, "move {} to {}", path, backup.display()),
+            Change::Conflict(message) => write!(f, "conflict: {}", message),
+            Change::Run(n) => write!(f, "run {} command(s)", n),
+            Change::RunUninstall(n) => write!(f, "run {} uninstall command(s)", n),
+            Change::Remove(path, None) => write!(f, "remove {}", path),
+            Change::Remove(path, Some(backup)) => {
+                write!(f, "remove {}, restoring {}", path, backup.display())
+            }
+            Change::Keep(path, reason) => write!(f, "keep {}, {}", path, reason),
+            Change::Unstow(dst, n) => write!(f, "remove {} link(s) under {}", n, dst),
+            Change::Uninstall(name) => write!(f, "remove the package {}", name),
+        }
+    }
+}
+
+/// How `dst` is cleared before a link or file is put there: a symlink is
+/// replaced, anything else is moved to its first free backup.
+pub fn clearing(dst: &Path) -> Option<Change> {
+    let meta = std::fs::symlink_metadata(dst).ok()?;
+    let path = dst.to_string_lossy().to_string();
+    Some(match meta.is_symlink() {
+        true => Change::Replace(path),
+        false => Change::Backup(path, super::backup_path(dst)),
+    })
+}
+
+/// Clears `dst` as planned by [`clearing`].
+pub(super) fn clear(dst: &Path) -> IoResult<()> {
+    match clearing(dst) {
+        Some(Change::Replace(_)) => std::fs::remove_file(dst),
+        Some(Change::Backup(_, backup)) => std::fs::rename(dst, backup),
+        _ => Ok(()),
+    }
+}
+
+/// Removing `dst` on undo, restoring its latest backup.
+fn revert(dst: &str) -> Change {
+    Change::Remove(dst.to_string(), latest_backup(Path::new(dst)))
+}
+
+impl Executer {
+    /// The changes running `task` makes, given how it drifts.
+    pub fn plan(&self, task: &Task, drifts: &[Drift]) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for drift in drifts {
+            let item = drift.item.clone();
+            let stowed = task.stow.iter().flatten().any(|s| s.dst == item);
+            let change = match drift.kind {
+                DriftKind::WrongTarget if stowed => Change::Conflict(drift.message.clone()),
+                DriftKind::Error => Change::Conflict(drift.message.clone()),
+                DriftKind::Missing if stowed => Change::Create(item),
+                DriftKind::Missing | DriftKind::WrongTarget => {
+                    clearing(Path::new(&item)).unwrap_or(Change::Create(item))
+                }
+                DriftKind::Modified if Path::new(&item).is_dir() => Change::Overwrite(item),
+                DriftKind::Modified => {
+                    clearing(Path::new(&item)).unwrap_or(Change::Overwrite(item))
+                }
+                DriftKind::NotInstalled => Change::Install(item),
+            };
+            if !changes.contains(&change) {
+                changes.push(change);
+            }
+        }
+        let commands = task.commands.iter().flatten().count();
+        if commands > 0 {
+            changes.push(Change::Run(commands));
+        }
+        changes
+    }
+    /// The changes undoing `task` makes.
+    pub fn undo_plan(&self, task: &Task) -> IoResult<Vec<Change>> {
+        let mut changes = Vec::new();
+        let commands = task.uninstall_commands.iter().flatten().count();
+        if commands > 0 {
+            changes.push(Change::RunUninstall(commands));
+        }
+        let present = |path: &str| std::fs::symlink_metadata(path).is_ok();
+        for link in task.slink.iter().flatten() {
+            if link.mode == LinkMode::Copy {
+                let (src, dst) = (Path::new(&link.src), Path::new(&link.dst));
+                let mut undone = Vec::new();
+                self.uncopy(src, dst, true, &mut undone)?;
+                // The files of a copied tree summed up, the backups restored
+                // listed one by one
+                let removed = undone.iter().any(|c| matches!(c, Change::Remove(_, None)));
+                let top = undone
+                    .iter()
+                    .any(|c| matches!(c, Change::Remove(p, _) if *p == link.dst));
+                if removed && !top {
+                    changes.push(Change::Remove(link.dst.clone(), None));
+                }
+                let kept = undone.iter().any(|c| matches!(c, Change::Keep(..)));
+                changes.extend(undone.into_iter().filter(|c| match c {
+                    Change::Remove(path, backup) => backup.is_some() || *path == link.dst,
+                    _ => false,
+                }));
+                if kept {
+                    changes.push(Change::Keep(
+                        link.dst.clone(),
+                        "modified since it was copied",
+                    ));
+                }
+                continue;
+            }
+            match self.revert_link(link)? {
+                Change::Remove(dst, None) if !present(&dst) => {}
+                change => changes.push(change),
+            }
+        }
+        for tpl in task.templates.iter().flatten() {
+            match self.revert_template(tpl)? {
+                Change::Remove(dst, None) if !present(&dst) => {}
+                change => changes.push(change),
+            }
+        }
+        for tree in task.stow.iter().flatten() {
+            let links = stow::installed(tree, &self.current_dir)?.len();
+            if links > 0 {
+                changes.push(Change::Unstow(tree.dst.clone(), links));
+            }
+        }
+        let names = removed_packages(task, self.distri.distri);
+        changes.extend(names.into_iter().map(Change::Uninstall));
+        Ok(changes)
+    }
+    /// How undo reverts a symlink still pointing into the repository, or a
+    /// hard link still sharing the inode of its source.
+    pub(super) fn revert_link(&self, link: &Symlink) -> IoResult<Change> {
+        use std::os::unix::fs::MetadataExt;
+        let dst = Path::new(&link.dst);
+        let Ok(meta) = std::fs::symlink_metadata(dst) else {
+            return Ok(revert(&link.dst));
+        };
+        let (ours, reason) = match link.mode {
+            LinkMode::Hardlink => (
+                std::fs::metadata(&link.src)
+                    .is_ok_and(|src| (src.dev(), src.ino()) == (meta.dev(), meta.ino())),
+                "no longer linked to the source",
+            ),
+            _ => (
+                meta.is_symlink() && std::fs::read_link(dst)?.starts_with(&self.current_dir),
+                "not linked into the repository",
+            ),
+        };
+        Ok(match ours {
+            true => revert(&link.dst),
+            false => Change::Keep(link.dst.clone(), reason),
+        })
+    }
+    /// How undo reverts a rendered template left as it was written.
+    pub(super) fn revert_template(&self, tpl: &Template) -> IoResult<Change> {
+        let ours = match std::fs::symlink_metadata(&tpl.dst) {
+            Ok(meta) => {
+                meta.is_file()
+                    && tpl.error.is_none()
+                    && std::fs::read_to_string(&tpl.dst)? == tpl.content
+            }
+            Err(_) => true,
+        };
+        Ok(match ours {
+            true => revert(&tpl.dst),
+            false => Change::Keep(tpl.dst.clone(), "modified since it was rendered"),
+        })
+    }
+}
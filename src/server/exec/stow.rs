@@ -0,0 +1,127 @@
+use crate::config::Stow;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+
+/// What has to be done to mirror a [`Stow`] source tree into its destination
+#[derive(Default, Debug)]
+pub struct Plan {
+    /// Real directories to create
+    pub dirs: Vec<PathBuf>,
+    /// `(src, dst)` symlinks to create
+    pub links: Vec<(PathBuf, PathBuf)>,
+    /// Destinations already in use by something else, with the reason
+    pub conflicts: Vec<(PathBuf, String)>,
+}
+
+impl Plan {
+    pub fn new(stow: &Stow) -> IoResult<Self> {
+        let ignore: Vec<_> = stow
+            .ignore
+            .iter()
+            .flatten()
+            .filter_map(|p| glob::Pattern::new(p).ok())
+            .collect();
+        let mut plan = Self::default();
+        plan.walk(
+            Path::new(&stow.src),
+            Path::new(&stow.dst),
+            Path::new(""),
+            stow.fold,
+            &ignore,
+        )?;
+        Ok(plan)
+    }
+    fn walk(
+        &mut self,
+        src: &Path,
+        dst: &Path,
+        rel: &Path,
+        fold: bool,
+        ignore: &[glob::Pattern],
+    ) -> IoResult<()> {
+        let mut entries = std::fs::read_dir(src)?.collect::<IoResult<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let rel = rel.join(entry.file_name());
+            if is_ignored(&rel, ignore) {
+                continue;
+            }
+            let (src, dst) = (entry.path(), dst.join(entry.file_name()));
+            let meta = std::fs::symlink_metadata(&dst).ok();
+            if meta.as_ref().is_some_and(|m| m.is_symlink()) {
+                let target = std::fs::read_link(&dst)?;
+                if target != src {
+                    self.conflicts
+                        .push((dst, format!("links to {}", target.display())));
+                }
+                continue;
+            }
+            match (src.is_dir(), meta) {
+                (true, None) if fold && !contains_ignored(&src, &rel, ignore)? => {
+                    self.links.push((src, dst));
+                }
+                (true, None) => {
+                    self.dirs.push(dst.clone());
+                    self.walk(&src, &dst, &rel, fold, ignore)?;
+                }
+                (true, Some(meta)) if meta.is_dir() => {
+                    self.walk(&src, &dst, &rel, fold, ignore)?;
+                }
+                (false, None) => self.links.push((src, dst)),
+                (_, Some(meta)) if meta.is_dir() => {
+                    self.conflicts.push((dst, "is a directory".to_string()))
+                }
+                (_, Some(_)) => self.conflicts.push((dst, "is a file".to_string())),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The symlinks of a mirrored tree still pointing into `repo`, which undoing
+/// the [`Stow`] removes.
+pub fn installed(stow: &Stow, repo: &Path) -> IoResult<Vec<PathBuf>> {
+    fn walk(src: &Path, dst: &Path, repo: &Path, links: &mut Vec<PathBuf>) -> IoResult<()> {
+        for entry in std::fs::read_dir(src)? {
+            let entry = entry?;
+            let dst = dst.join(entry.file_name());
+            match std::fs::symlink_metadata(&dst) {
+                Ok(meta)
+                    if meta.is_symlink()
+                        && std::fs::read_link(&dst).is_ok_and(|t| t.starts_with(repo)) =>
+                {
+                    links.push(dst)
+                }
+                Ok(meta) if meta.is_dir() && entry.path().is_dir() => {
+                    walk(&entry.path(), &dst, repo, links)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    let mut links = Vec::new();
+    walk(Path::new(&stow.src), Path::new(&stow.dst), repo, &mut links)?;
+    Ok(links)
+}
+
+fn is_ignored(rel: &Path, ignore: &[glob::Pattern]) -> bool {
+    let name = rel.file_name().unwrap_or_default();
+    ignore
+        .iter()
+        .any(|p| p.matches_path(rel) || p.matches_path(Path::new(name)))
+}
+
+/// Whether an ignored file is somewhere under `src`, which prevents folding it.
+fn contains_ignored(src: &Path, rel: &Path, ignore: &[glob::Pattern]) -> IoResult<bool> {
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let rel = rel.join(entry.file_name());
+        if is_ignored(&rel, ignore)
+            || (entry.path().is_dir() && contains_ignored(&entry.path(), &rel, ignore)?)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
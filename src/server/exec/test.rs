@@ -8,7 +8,7 @@ fn symlink() {
     let testdir = "unit_exec_symlink_test";
     let hook = std::panic::take_hook();
     panic::set_hook(Box::new(move |info| {
-        remove_dir_all(testdir).unwrap();
+        let _ = remove_dir_all(testdir);
         hook(info);
     }));
     fs::create_dir(testdir).unwrap();
@@ -25,5 +25,434 @@ fn symlink() {
     let dst = testdir.to_string() + "/parent/test_link.txt";
     exec.symlink(&src, &dst).unwrap();
     assert_eq!(fs::read_to_string(&dst).unwrap(), "Hello, World!");
+    let dst = testdir.to_string() + "/mine.txt";
+    fs::write(&dst, "mine").unwrap();
+    fs::write(dst.clone() + ".easycfg.bak", "older").unwrap();
+    assert_eq!(
+        super::plan::clearing(std::path::Path::new(&dst)),
+        Some(super::Change::Backup(
+            dst.clone(),
+            (dst.clone() + ".easycfg.bak.1").into()
+        ))
+    );
+    exec.symlink(&src, &dst).unwrap();
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "Hello, World!");
+    assert_eq!(
+        fs::read_to_string(dst.clone() + ".easycfg.bak").unwrap(),
+        "older"
+    );
+    assert_eq!(
+        fs::read_to_string(dst.clone() + ".easycfg.bak.1").unwrap(),
+        "mine"
+    );
+    let link = crate::config::Symlink {
+        src: src.to_string_lossy().to_string(),
+        dst: dst.clone(),
+        ..Default::default()
+    };
+    let change = exec.revert_link(&link).unwrap();
+    assert_eq!(
+        change.to_string(),
+        format!("remove {}, restoring {}.easycfg.bak.1", dst, dst)
+    );
+    assert_eq!(exec.restore(change).unwrap(), "removed, backup restored");
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "mine");
+    assert!(!std::path::Path::new(&(dst.clone() + ".easycfg.bak.1")).exists());
+    fs::remove_dir_all(testdir).unwrap();
+}
+
+#[test]
+fn template() {
+    let testdir = "unit_exec_template_test";
+    let hook = std::panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = remove_dir_all(testdir);
+        hook(info);
+    }));
+    fs::create_dir(testdir).unwrap();
+    let mut exec = super::Executer::new(
+        crate::config::Distri::Unknown,
+        std::env::current_dir().unwrap(),
+    );
+    let dst = testdir.to_string() + "/parent/gitconfig";
+    let mut tpl = crate::config::Template {
+        src: "gitconfig".to_string(),
+        dst: dst.clone(),
+        content: "email = a\n".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(exec.template(&tpl).unwrap(), "created");
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "email = a\n");
+    assert_eq!(exec.template(&tpl).unwrap(), "unchanged");
+    tpl.content = "email = b\n".to_string();
+    exec.dry_run = true;
+    let change = exec.template(&tpl).unwrap();
+    assert!(change.starts_with("updated\n"));
+    assert!(change.contains("-email = a\n+email = b\n"));
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "email = a\n");
+    exec.dry_run = false;
+    exec.template(&tpl).unwrap();
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "email = b\n");
+    let backup = dst.clone() + super::BACKUP_SUFFIX;
+    assert_eq!(fs::read_to_string(&backup).unwrap(), "email = a\n");
+    let change = exec.revert_template(&tpl).unwrap();
+    assert_eq!(
+        change,
+        super::Change::Remove(dst.clone(), Some(backup.clone().into()))
+    );
+    exec.restore(change).unwrap();
+    assert_eq!(fs::read_to_string(&dst).unwrap(), "email = a\n");
+    assert!(!std::path::Path::new(&backup).exists());
+    fs::remove_file(&dst).unwrap();
+    fs::create_dir(&dst).unwrap();
+    exec.template(&tpl).unwrap();
+    assert!(std::path::Path::new(&(dst.clone() + super::BACKUP_SUFFIX)).is_dir());
+    tpl.error = Some("template gitconfig: undefined variable".to_string());
+    assert!(exec.template(&tpl).is_err());
     fs::remove_dir_all(testdir).unwrap();
 }
+
+#[test]
+fn copy() {
+    use std::os::unix::fs::PermissionsExt;
+    let testdir = "unit_exec_copy_test";
+    let hook = std::panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = remove_dir_all(testdir);
+        hook(info);
+    }));
+    fs::create_dir_all(testdir.to_string() + "/src/sub").unwrap();
+    fs::write(testdir.to_string() + "/src/a", "a").unwrap();
+    fs::write(testdir.to_string() + "/src/sub/b", "b").unwrap();
+    fs::set_permissions(
+        testdir.to_string() + "/src/a",
+        fs::Permissions::from_mode(0o700),
+    )
+    .unwrap();
+    let mut exec = super::Executer::new(
+        crate::config::Distri::Unknown,
+        std::env::current_dir().unwrap(),
+    );
+    let src = testdir.to_string() + "/src";
+    let dst = testdir.to_string() + "/dst";
+    assert_eq!(exec.copy(&src, &dst).unwrap(), 2);
+    assert_eq!(fs::read_to_string(dst.clone() + "/sub/b").unwrap(), "b");
+    let mode = fs::metadata(dst.clone() + "/a")
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o700);
+    assert_eq!(exec.copy(&src, &dst).unwrap(), 0);
+    fs::write(testdir.to_string() + "/src/sub/b", "c").unwrap();
+    assert_eq!(exec.copy(&src, &dst).unwrap(), 1);
+    assert_eq!(fs::read_to_string(dst.clone() + "/sub/b").unwrap(), "c");
+    assert_eq!(
+        fs::read_to_string(dst.clone() + "/sub/b" + super::BACKUP_SUFFIX).unwrap(),
+        "b"
+    );
+    let kept = testdir.to_string() + "/kept";
+    fs::create_dir(&kept).unwrap();
+    fs::write(kept.clone() + "/keep", "mine").unwrap();
+    assert_eq!(exec.copy(src.clone() + "/a", &kept).unwrap(), 1);
+    assert_eq!(fs::read_to_string(&kept).unwrap(), "a");
+    let backup = kept.clone() + super::BACKUP_SUFFIX;
+    assert_eq!(
+        fs::read_to_string(backup.clone() + "/keep").unwrap(),
+        "mine"
+    );
+    let mut changes = Vec::new();
+    let (a, kept) = (
+        std::path::Path::new(&src).join("a"),
+        std::path::Path::new(&kept),
+    );
+    assert!(!exec.uncopy(&a, kept, false, &mut changes).unwrap());
+    assert_eq!(
+        changes,
+        [super::Change::Remove(
+            kept.to_string_lossy().to_string(),
+            Some(backup.into())
+        )]
+    );
+    assert_eq!(fs::read_to_string(kept.join("keep")).unwrap(), "mine");
+
+    let file = testdir.to_string() + "/file";
+    exec.symlink(fs::canonicalize(&src).unwrap().join("a"), &file)
+        .unwrap();
+    assert_eq!(exec.copy(src.clone() + "/a", &file).unwrap(), 1);
+    assert!(!fs::symlink_metadata(&file).unwrap().is_symlink());
+    exec.hardlink(src.clone() + "/a", &file).unwrap();
+    assert_eq!(
+        fs::read_to_string(file.clone() + super::BACKUP_SUFFIX).unwrap(),
+        "a"
+    );
+    exec.hardlink(src.clone() + "/a", &file).unwrap();
+    fs::write(&file, "d").unwrap();
+    assert_eq!(fs::read_to_string(src.clone() + "/a").unwrap(), "d");
+    assert!(exec.hardlink(&src, testdir.to_string() + "/dir").is_err());
+    fs::remove_dir_all(testdir).unwrap();
+}
+
+#[test]
+fn stow() {
+    let testdir = "unit_exec_stow_test";
+    let hook = std::panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = remove_dir_all(testdir);
+        hook(info);
+    }));
+    fs::create_dir_all(testdir.to_string() + "/src/nvim/lua").unwrap();
+    fs::create_dir_all(testdir.to_string() + "/src/fish").unwrap();
+    fs::create_dir_all(testdir.to_string() + "/dst/fish").unwrap();
+    fs::write(testdir.to_string() + "/src/nvim/init.lua", "").unwrap();
+    fs::write(testdir.to_string() + "/src/nvim/lua/opts.lua", "").unwrap();
+    fs::write(testdir.to_string() + "/src/nvim/README.md", "").unwrap();
+    fs::write(testdir.to_string() + "/src/fish/config.fish", "").unwrap();
+    fs::write(testdir.to_string() + "/dst/fish/local.fish", "").unwrap();
+    let src = fs::canonicalize(testdir.to_string() + "/src").unwrap();
+    let dst = fs::canonicalize(testdir.to_string() + "/dst").unwrap();
+    let mut exec = super::Executer::new(
+        crate::config::Distri::Unknown,
+        std::env::current_dir().unwrap(),
+    );
+    let mut stow = crate::config::Stow {
+        src: src.to_string_lossy().to_string(),
+        dst: dst.to_string_lossy().to_string(),
+        ignore: Some(vec!["*.md".to_string()]),
+        ..Default::default()
+    };
+
+    exec.dry_run = true;
+    assert_eq!(exec.stow(&stow).unwrap(), "3 link(s) created");
+    assert!(!dst.join("nvim").exists());
+    exec.dry_run = false;
+    exec.stow(&stow).unwrap();
+    assert!(!fs::symlink_metadata(dst.join("nvim")).unwrap().is_symlink());
+    assert!(fs::symlink_metadata(dst.join("nvim/lua/opts.lua"))
+        .unwrap()
+        .is_symlink());
+    assert!(!dst.join("nvim/README.md").exists());
+    assert!(fs::symlink_metadata(dst.join("fish/config.fish"))
+        .unwrap()
+        .is_symlink());
+    assert!(dst.join("fish/local.fish").is_file());
+    assert_eq!(exec.stow(&stow).unwrap(), "unchanged");
+
+    // `lua` holds no ignored file and is linked whole
+    fs::remove_dir_all(dst.join("nvim")).unwrap();
+    stow.fold = true;
+    exec.stow(&stow).unwrap();
+    assert!(!fs::symlink_metadata(dst.join("nvim")).unwrap().is_symlink());
+    assert!(fs::symlink_metadata(dst.join("nvim/lua"))
+        .unwrap()
+        .is_symlink());
+
+    // conflicts are all reported and nothing is changed
+    fs::remove_file(dst.join("nvim/init.lua")).unwrap();
+    fs::write(dst.join("nvim/init.lua"), "").unwrap();
+    fs::remove_file(dst.join("fish/config.fish")).unwrap();
+    fs::create_dir(dst.join("fish/config.fish")).unwrap();
+    fs::write(src.join("fish/functions.fish"), "").unwrap();
+    let err = exec.stow(&stow).unwrap_err().to_string();
+    assert!(err.contains("init.lua: is a file"));
+    assert!(err.contains("config.fish: is a directory"));
+    assert!(!dst.join("fish/functions.fish").exists());
+    fs::remove_dir_all(testdir).unwrap();
+}
+
+#[test]
+fn undo() {
+    let testdir = "unit_exec_undo_test";
+    let hook = std::panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = remove_dir_all(testdir);
+        hook(info);
+    }));
+    fs::create_dir_all(testdir.to_string() + "/repo/tree/sub").unwrap();
+    fs::create_dir_all(testdir.to_string() + "/home").unwrap();
+    fs::write(testdir.to_string() + "/repo/rc", "repo").unwrap();
+    fs::write(testdir.to_string() + "/repo/tree/sub/file", "").unwrap();
+    fs::write(testdir.to_string() + "/home/rc", "mine").unwrap();
+    fs::write(testdir.to_string() + "/home/hard", "old").unwrap();
+    fs::write(testdir.to_string() + "/home/copy", "old copy").unwrap();
+    let repo = fs::canonicalize(testdir.to_string() + "/repo").unwrap();
+    let home = fs::canonicalize(testdir.to_string() + "/home").unwrap();
+    let path = |p: &std::path::Path| p.to_string_lossy().to_string();
+    let task = crate::config::Task {
+        name: "undo".to_string(),
+        path: Some(path(&repo)),
+        uninstall_commands: Some(vec!["touch uninstalled".into()]),
+        slink: Some(vec![
+            (
+                path(&repo.join("rc")).as_str(),
+                path(&home.join("rc")).as_str(),
+            )
+                .into(),
+            (
+                path(&repo.join("rc")).as_str(),
+                path(&home.join("other")).as_str(),
+            )
+                .into(),
+            crate::config::Symlink {
+                src: path(&repo.join("rc")),
+                dst: path(&home.join("hard")),
+                mode: crate::config::LinkMode::Hardlink,
+                ..Default::default()
+            },
+            crate::config::Symlink {
+                src: path(&repo.join("tree")),
+                dst: path(&home.join("copy")),
+                mode: crate::config::LinkMode::Copy,
+                ..Default::default()
+            },
+        ]),
+        templates: Some(vec![
+            crate::config::Template {
+                dst: path(&home.join("kept")),
+                content: "rendered".to_string(),
+                ..Default::default()
+            },
+            crate::config::Template {
+                dst: path(&home.join("removed")),
+                content: "rendered".to_string(),
+                ..Default::default()
+            },
+        ]),
+        stow: Some(vec![crate::config::Stow {
+            src: path(&repo.join("tree")),
+            dst: path(&home),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    let mut exec = super::Executer::new(crate::config::Distri::Unknown, repo.clone());
+    exec.exec(&task).1.unwrap();
+    assert_eq!(fs::read_to_string(home.join("rc")).unwrap(), "repo");
+    assert_eq!(
+        fs::read_to_string(home.join("rc.easycfg.bak")).unwrap(),
+        "mine"
+    );
+    fs::write(home.join("kept"), "edited").unwrap();
+    fs::remove_file(home.join("other")).unwrap();
+    exec.symlink(home.join("rc.easycfg.bak"), home.join("other"))
+        .unwrap();
+
+    let plan = exec.undo_plan(&task).unwrap();
+    assert!(plan.contains(&super::Change::Keep(
+        path(&home.join("kept")),
+        "modified since it was rendered"
+    )));
+    assert!(plan.contains(&super::Change::Remove(
+        path(&home.join("hard")),
+        Some(home.join("hard.easycfg.bak"))
+    )));
+    assert!(plan.contains(&super::Change::Remove(
+        path(&home.join("copy")),
+        Some(home.join("copy.easycfg.bak"))
+    )));
+    exec.dry_run = true;
+    exec.undo(&task).1.unwrap();
+    assert!(fs::symlink_metadata(home.join("sub/file")).is_ok());
+    exec.dry_run = false;
+    exec.undo(&task).1.unwrap();
+    assert!(repo.join("uninstalled").is_file());
+    assert_eq!(fs::read_to_string(home.join("rc")).unwrap(), "mine");
+    assert!(!home.join("rc.easycfg.bak").exists());
+    assert!(fs::symlink_metadata(home.join("other"))
+        .unwrap()
+        .is_symlink());
+    assert!(!home.join("sub").exists());
+    assert_eq!(fs::read_to_string(home.join("hard")).unwrap(), "old");
+    assert_eq!(fs::read_to_string(home.join("copy")).unwrap(), "old copy");
+    assert!(!home.join("removed").exists());
+    assert_eq!(fs::read_to_string(home.join("kept")).unwrap(), "edited");
+    fs::remove_dir_all(testdir).unwrap();
+}
+
+#[test]
+fn drift() {
+    let testdir = "unit_exec_drift_test";
+    let hook = std::panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = remove_dir_all(testdir);
+        hook(info);
+    }));
+    fs::create_dir_all(testdir.to_string() + "/repo").unwrap();
+    fs::write(testdir.to_string() + "/repo/rc", "rc").unwrap();
+    let repo = fs::canonicalize(testdir.to_string() + "/repo").unwrap();
+    let path = |name: &str| repo.join(name).to_string_lossy().to_string();
+    let task = crate::config::Task {
+        name: "drift".to_string(),
+        slink: Some(vec![
+            (path("rc").as_str(), path("linked").as_str()).into(),
+            (path("rc").as_str(), path("elsewhere").as_str()).into(),
+            (path("rc").as_str(), path("missing").as_str()).into(),
+        ]),
+        templates: Some(vec![crate::config::Template {
+            src: path("tpl"),
+            dst: path("rendered"),
+            content: "a\n".to_string(),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    let mut exec = super::Executer::new(crate::config::Distri::Unknown, repo.clone());
+    exec.symlink(path("rc"), path("linked")).unwrap();
+    exec.symlink(path("linked"), path("elsewhere")).unwrap();
+    fs::write(path("rendered"), "edited\n").unwrap();
+    let drifts = exec.drift(&task);
+    let kinds: Vec<_> = drifts.iter().map(|d| d.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            super::DriftKind::WrongTarget,
+            super::DriftKind::Missing,
+            super::DriftKind::Modified
+        ]
+    );
+    assert!(drifts[0].to_string().contains("elsewhere links to"));
+    assert_eq!(drifts[1].item, path("missing"));
+    fs::write(path("rendered"), "a\n").unwrap();
+    assert_eq!(exec.drift(&task).len(), 2);
+    let broken = crate::config::Task {
+        name: "broken".to_string(),
+        slink: Some(vec![crate::config::Symlink {
+            src: path("nosrc"),
+            dst: path("linked"),
+            mode: crate::config::LinkMode::Hardlink,
+            ..Default::default()
+        }]),
+        templates: Some(vec![crate::config::Template {
+            dst: path("rendered"),
+            error: Some("template tpl: undefined variable".to_string()),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    let drifts = exec.drift(&broken);
+    assert!(drifts.iter().all(|d| d.kind == super::DriftKind::Error));
+    assert_eq!(drifts[0].item, path("linked"));
+    assert!(drifts[0].message.contains("nosrc"));
+    assert_eq!(drifts[1].message, "template tpl: undefined variable");
+    fs::remove_dir_all(testdir).unwrap();
+}
+
+#[test]
+fn commands() {
+    let mut exec = super::Executer::new(
+        crate::config::Distri::Unknown,
+        std::env::current_dir().unwrap(),
+    );
+    let mut task = crate::config::Task {
+        name: "commands".to_string(),
+        path: Some("src".to_string()),
+        commands: Some(vec!["echo one".into(), "exit 3".into(), "echo two".into()]),
+        ..Default::default()
+    };
+    let (outputs, result) = exec.exec(&task);
+    assert!(result.unwrap_err().to_string().contains("exit 3 failed"));
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[1].exit_code, Some(3));
+    assert_eq!(outputs[1].status, crate::server::step::StepStatus::Failure);
+    task.commands = Some(vec!["true".into()]);
+    exec.exec(&task).1.unwrap();
+}
@@ -0,0 +1,177 @@
+//! One log file per run under `$XDG_STATE_HOME/easycfg/logs`, holding the
+//! output of every step and the tracing events.
+use super::state::state_dir;
+use super::step::Step;
+use std::fs::File;
+use std::io::{Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many run logs are kept, the oldest being removed first
+pub const KEEP_RUNS: usize = 20;
+
+/// How much of the tracing events is kept until the file is created
+const PENDING_MAX: usize = 64 * 1024;
+
+/// The log of a run, created with the first step executed so that a session
+/// running nothing leaves no log behind.
+#[derive(Debug, Clone)]
+pub struct RunLog {
+    path: PathBuf,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    keep: usize,
+    file: Option<File>,
+    /// The tracing events written before the file was created
+    pending: Vec<u8>,
+}
+
+impl RunLog {
+    pub fn default_dir() -> Option<PathBuf> {
+        state_dir().map(|dir| dir.join("logs"))
+    }
+    /// The log of a new run in `dir`, keeping the last `keep` runs once it
+    /// is created.
+    pub fn new(dir: &Path, keep: usize) -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            path: dir.join(format!("{:010}-{}.log", secs, std::process::id())),
+            inner: Arc::new(Mutex::new(Inner {
+                keep,
+                file: None,
+                pending: Vec::new(),
+            })),
+        }
+    }
+    /// The run logs in `dir`, the newest first.
+    pub fn list(dir: &Path) -> IoResult<Vec<PathBuf>> {
+        let mut logs = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                logs.push(path);
+            }
+        }
+        logs.sort_by(|a, b| b.cmp(a));
+        Ok(logs)
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Whether the file was created, by a first step.
+    pub fn created(&self) -> bool {
+        self.lock().file.is_some()
+    }
+    /// Another handle on the log, for the tracing events.
+    pub fn writer(&self) -> RunLogWriter {
+        RunLogWriter(self.clone())
+    }
+    /// Writes the output of `steps`, creating the file and removing the
+    /// oldest runs first if needed.
+    pub fn write_steps(&self, steps: &[Step]) -> IoResult<()> {
+        let inner = &mut *self.lock();
+        let file = match &mut inner.file {
+            Some(file) => file,
+            None => {
+                let mut file = self.create(inner.keep)?;
+                file.write_all(&std::mem::take(&mut inner.pending))?;
+                inner.file.insert(file)
+            }
+        };
+        for step in steps {
+            let (out, err) = step.lines();
+            for line in out {
+                writeln!(file, "{}", line)?;
+            }
+            for line in err {
+                writeln!(file, "! {}", line)?;
+            }
+        }
+        Ok(())
+    }
+    fn create(&self, keep: usize) -> IoResult<File> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        let file = File::options().create(true).append(true).open(&self.path)?;
+        for old in Self::list(dir)?.into_iter().skip(keep) {
+            std::fs::remove_file(old)?;
+        }
+        Ok(file)
+    }
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Writes the tracing events to a [`RunLog`], keeping them in memory until
+/// its file is created.
+#[derive(Debug, Clone)]
+pub struct RunLogWriter(RunLog);
+
+impl Write for RunLogWriter {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let mut inner = self.0.lock();
+        match &mut inner.file {
+            Some(file) => file.write(buf),
+            None => {
+                if inner.pending.len() + buf.len() <= PENDING_MAX {
+                    inner.pending.extend_from_slice(buf);
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        match &mut self.0.lock().file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::step::StepKind;
+    use std::fs::remove_dir_all;
+    use std::panic;
+
+    #[test]
+    fn rotation() {
+        let testdir = "unit_runlog_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        let dir = Path::new(testdir);
+        std::fs::create_dir(dir).unwrap();
+        for old in ["0000000001-1.log", "0000000002-1.log", "0000000003-1.log"] {
+            std::fs::write(dir.join(old), "").unwrap();
+        }
+        let log = RunLog::new(dir, 3);
+        log.writer().write_all(b"event\n").unwrap();
+        assert!(!log.path().exists());
+        assert_eq!(RunLog::list(dir).unwrap().len(), 3);
+        let mut step = Step::new("zsh", StepKind::Exec, "true".to_string());
+        step.stdout = b"out".to_vec();
+        step.stderr = b"err".to_vec();
+        log.write_steps(&[step]).unwrap();
+        let logs = RunLog::list(dir).unwrap();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs[0], log.path());
+        assert!(!dir.join("0000000001-1.log").exists());
+        assert_eq!(
+            std::fs::read_to_string(log.path()).unwrap(),
+            "event\n[exec] true\nout\n! err\n"
+        );
+        remove_dir_all(testdir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! What is known about the previous runs, kept in
+//! `$XDG_STATE_HOME/easycfg/state.json` between sessions.
+use crate::config::{LinkMode, Task};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// The last run of a task
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TaskState {
+    /// Seconds since the Unix epoch
+    pub last_run: u64,
+    pub outcome: Outcome,
+    /// Hash of the rendered task, see [`hash`]
+    pub hash: String,
+    /// The git commit of the config directory, if it is a repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Files and links created by the task
+    pub artifacts: Vec<String>,
+}
+
+/// How a task relates to its last run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum TaskStatus {
+    /// Never run
+    Pending,
+    /// Run successfully with the current definition
+    Applied,
+    /// Run successfully, but the rendered task changed since
+    Stale,
+    Failed,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    pub tasks: BTreeMap<String, TaskState>,
+    /// The git commit of the config directory, recorded with each run
+    #[serde(skip)]
+    pub revision: Option<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl State {
+    /// The default location of the state file
+    pub fn default_path() -> Option<PathBuf> {
+        state_dir().map(|dir| dir.join("state.json"))
+    }
+    /// Reads the state file, a missing file being an empty state.
+    pub fn load(path: &Path) -> IoResult<Self> {
+        let mut state = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        state.path = Some(path.to_path_buf());
+        Ok(state)
+    }
+    pub fn save(&self) -> IoResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+    pub fn status(&self, task: &Task) -> TaskStatus {
+        self.status_hashed(task, &hash(task))
+    }
+    /// The status of `task` given its [`hash`], which reads the files it
+    /// copies.
+    pub fn status_hashed(&self, task: &Task, hash: &str) -> TaskStatus {
+        match self.tasks.get(&task.name) {
+            None => TaskStatus::Pending,
+            Some(state) if state.outcome == Outcome::Failure => TaskStatus::Failed,
+            Some(state) if state.hash == hash => TaskStatus::Applied,
+            Some(_) => TaskStatus::Stale,
+        }
+    }
+    pub fn record(&mut self, task: &Task, outcome: Outcome, artifacts: Vec<String>) {
+        let last_run = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.tasks.insert(
+            task.name.clone(),
+            TaskState {
+                last_run,
+                outcome,
+                hash: hash(task),
+                revision: self.revision.clone(),
+                artifacts,
+            },
+        );
+    }
+}
+
+/// `$XDG_STATE_HOME/easycfg`, or its default
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        .map(|dir| dir.join("easycfg"))
+}
+
+/// The commit checked out in `dir`, when it is a git repository.
+pub fn revision(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let revision = String::from_utf8(output.stdout).ok()?;
+    output.status.success().then(|| revision.trim().to_string())
+}
+
+/// Hash of the rendered task: its definition once the variables are
+/// interpolated, its rendered templates and the content of the files it copies.
+pub fn hash(task: &Task) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(task).unwrap_or_default());
+    for tpl in task.templates.iter().flatten() {
+        hasher.update(&tpl.content);
+    }
+    let copies = task.slink.iter().flatten();
+    for link in copies.filter(|l| l.mode == LinkMode::Copy) {
+        hash_tree(&mut hasher, Path::new(&link.src));
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Feeds the paths and contents of the files under `path` to `hasher`.
+fn hash_tree(hasher: &mut Sha256, path: &Path) {
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    match std::fs::read_dir(path) {
+        Ok(entries) => {
+            let mut paths: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+            paths.sort();
+            for path in paths {
+                hash_tree(hasher, &path);
+            }
+        }
+        Err(_) => hasher.update(std::fs::read(path).unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::remove_dir_all;
+    use std::panic;
+
+    #[test]
+    fn status() {
+        let testdir = "unit_state_test";
+        let hook = std::panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = remove_dir_all(testdir);
+            hook(info);
+        }));
+        let path = Path::new(testdir).join("easycfg/state.json");
+        let mut state = State::load(&path).unwrap();
+        let mut task = Task {
+            name: "zsh".to_string(),
+            commands: Some(vec!["true".into()]),
+            ..Default::default()
+        };
+        assert_eq!(state.status(&task), TaskStatus::Pending);
+        state.record(&task, Outcome::Success, vec!["~/.zshrc".to_string()]);
+        state.save().unwrap();
+
+        let mut state = State::load(&path).unwrap();
+        assert_eq!(state.status(&task), TaskStatus::Applied);
+        assert_eq!(state.tasks["zsh"].artifacts, vec!["~/.zshrc"]);
+        task.commands = Some(vec!["false".into()]);
+        assert_eq!(state.status(&task), TaskStatus::Stale);
+        state.record(&task, Outcome::Failure, Vec::new());
+        assert_eq!(state.status(&task), TaskStatus::Failed);
+
+        let src = Path::new(testdir).join("rc");
+        std::fs::write(&src, "a").unwrap();
+        let task = Task {
+            name: "rc".to_string(),
+            slink: Some(vec![crate::config::Symlink {
+                src: src.to_string_lossy().to_string(),
+                dst: "~/.rc".to_string(),
+                mode: LinkMode::Copy,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        state.revision = Some("abc".to_string());
+        state.record(&task, Outcome::Success, Vec::new());
+        assert_eq!(state.tasks["rc"].revision.as_deref(), Some("abc"));
+        assert_eq!(state.status(&task), TaskStatus::Applied);
+        std::fs::write(&src, "b").unwrap();
+        assert_eq!(state.status(&task), TaskStatus::Stale);
+        remove_dir_all(testdir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! The record of each step of a run, as shown in the interface or printed by
+//! `--output json`.
+use crate::config::LinkMode;
+use serde::{Serialize, Serializer};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum StepKind {
+    /// The whole task, when it is skipped
+    Task,
+    Symlink,
+    Copy,
+    Hardlink,
+    Template,
+    Stow,
+    Exec,
+    Unlink,
+    /// A copied or rendered file, on undo
+    Remove,
+    Unstow,
+    Install,
+    Uninstall,
+}
+
+impl From<LinkMode> for StepKind {
+    fn from(mode: LinkMode) -> Self {
+        match mode {
+            LinkMode::Symlink => StepKind::Symlink,
+            LinkMode::Copy => StepKind::Copy,
+            LinkMode::Hardlink => StepKind::Hardlink,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum StepStatus {
+    Success,
+    Failure,
+    /// Only reported, in dry-run
+    DryRun,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub task: String,
+    #[serde(rename = "step")]
+    pub kind: StepKind,
+    /// The command line, or `src -> dst` for files
+    pub command: String,
+    pub status: StepStatus,
+    /// Exit code of the process run by the step, if any
+    pub exit_code: Option<i32>,
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+    #[serde(serialize_with = "lossy")]
+    pub stdout: Vec<u8>,
+    #[serde(serialize_with = "lossy")]
+    pub stderr: Vec<u8>,
+}
+
+impl Step {
+    pub fn new(task: &str, kind: StepKind, command: String) -> Self {
+        Self {
+            task: task.to_string(),
+            kind,
+            command,
+            status: StepStatus::Success,
+            exit_code: None,
+            duration: Duration::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+    /// What the step did, such as `[dry-run] [symlink] src -> dst`.
+    pub fn header(&self) -> String {
+        let status = match self.status {
+            StepStatus::DryRun => "[dry-run] ",
+            StepStatus::Skipped => "[skip] ",
+            _ => "",
+        };
+        format!("{}[{}] {}", status, self.kind, self.command)
+    }
+    /// The lines shown for the step: its header followed by its output, and
+    /// its errors.
+    pub fn lines(&self) -> (Vec<String>, Vec<String>) {
+        let lines = |bytes: &[u8]| {
+            String::from_utf8_lossy(bytes)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let mut out = vec![self.header()];
+        out.extend(lines(&self.stdout));
+        (out, lines(&self.stderr))
+    }
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+fn lossy<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(bytes))
+}
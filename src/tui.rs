@@ -1,44 +1,220 @@
-use super::server;
+mod fuzzy;
+mod logs;
+mod scroll;
+
+use super::config::TagFilter;
+use super::config::Task;
+use super::server::runlog::RunLog;
+use super::server::state::TaskStatus;
+use super::server::step::Step;
+use super::server::{self, Change, Drift, DriftKind};
 use crossterm::{execute, terminal::*};
 use ratatui::prelude::*;
-use std::io::{self, stdout, Stdout};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, stdout, Result as IoResult, Stdout};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 /// A type alias for the terminal type used in this application
 pub type Backend = Terminal<CrosstermBackend<Stdout>>;
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+    MouseEvent, MouseEventKind,
+};
 
 use ratatui::widgets::{block::*, *};
 
+pub use logs::LogBuffer;
+use scroll::Scroll;
+use tracing::Level;
+
+/// The levels of the debug view, from the least verbose
+const LEVELS: [Level; 4] = [Level::ERROR, Level::WARN, Level::INFO, Level::DEBUG];
+
+/// The pane receiving the navigation keys, switched with Tab
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Focus {
+    #[default]
+    Tasks,
+    Output,
+    Error,
+}
+
+/// What to do with a task
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Job {
+    Exec,
+    Undo,
+}
+
+impl Job {
+    fn run(self, server: &mut server::Server, index: usize) -> (Vec<Step>, IoResult<()>) {
+        match self {
+            Job::Exec => server.exec_by_index(index),
+            Job::Undo => server.undo_by_index(index),
+        }
+    }
+}
+
+/// Jobs waiting for the user to confirm them
+#[derive(Debug)]
+struct Confirm {
+    job: Job,
+    indexes: Vec<usize>,
+    /// The changes planned, one per line
+    summary: Vec<Line<'static>>,
+}
+
+/// The last run of a task in this session
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Run {
+    #[default]
+    /// Not run yet in this session
+    Idle,
+    /// Waiting for the tasks before it in the queue
+    Queued,
+    Running,
+    Done {
+        success: bool,
+        duration: Duration,
+    },
+}
+
+/// The logs of the last run of a task
+#[derive(Debug, Default)]
+struct Output {
+    out: Vec<String>,
+    err: Vec<String>,
+    run: Run,
+}
+
 #[derive(Debug, Default)]
 pub struct Tui {
     server: server::Server,
-    outlog: Vec<String>,
-    errlog: Vec<String>,
+    /// The output of each task, by index in the config
+    outputs: Vec<Output>,
+    /// A past run or a message, shown instead of the selected task until
+    /// the selection changes
+    past: Option<Output>,
+    /// The jobs left to run, the first one once it is drawn as running
+    queue: VecDeque<(usize, Job)>,
+    /// The position of the running job in the queue and its length
+    progress: Option<(usize, usize)>,
+    /// Run the queued jobs as a dry run
+    dry_run: bool,
+    confirm: Option<Confirm>,
     tasklist: ListState,
-    outputlist: ListState,
-    errorlist: ListState,
+    /// The tasks marked to run together, by index in the config
+    marked: BTreeSet<usize>,
+    /// The fuzzy search over the tasks, when filtering them
+    search: Option<String>,
+    /// Whether the keys are typed in the search
+    typing: bool,
+    focus: Focus,
+    outscroll: Scroll,
+    errscroll: Scroll,
+    /// Where the log panes were last drawn, to scroll them with the mouse
+    outarea: Rect,
+    errarea: Rect,
+    filter: TagFilter,
+    tags: Vec<String>,
+    tag_cursor: usize,
+    /// The task shown in the detail popup, when shown
+    detail: Option<usize>,
+    /// How the tasks differ from the machine, compared once until they run
+    drifts: BTreeMap<usize, Vec<Drift>>,
+    /// The past run logs when browsing them, the newest first
+    runs: Option<(Vec<PathBuf>, ListState)>,
+    logs: LogBuffer,
+    /// The level of the debug view replacing the error log, when shown
+    debug: Option<Level>,
     exit: bool,
 }
 impl Tui {
     pub fn new(server: server::Server) -> Self {
         Self {
+            tags: server.cfg.tags(),
+            outputs: server.cfg.tasks.iter().map(|_| Output::default()).collect(),
             server,
             tasklist: ListState::default().with_selected(Some(0)),
             ..Default::default()
         }
     }
+    pub fn tag_filter(mut self, filter: TagFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+    /// Shows the tracing events recorded by `logs` in the debug view
+    pub fn logs(mut self, logs: LogBuffer) -> Self {
+        self.logs = logs;
+        self
+    }
+    /// indexes of the tasks passing the tag filter
+    fn visible(&self) -> Vec<usize> {
+        (0..self.server.cfg.tasks.len())
+            .filter(|i| self.filter.matches(&self.server.cfg.tasks[*i]))
+            .filter(|i| self.found(&self.server.cfg.tasks[*i]).is_some())
+            .collect()
+    }
+    /// Where the search matches a task: its name, or else one of its tags or
+    /// commands, with the positions of the matched chars.
+    fn found(&self, task: &Task) -> Option<(Option<String>, Vec<usize>)> {
+        let Some(search) = &self.search else {
+            return Some((None, Vec::new()));
+        };
+        if let Some(positions) = fuzzy::positions(search, &task.name) {
+            return Some((None, positions));
+        }
+        let tags = task.tags.iter().flatten().map(|t| format!("#{}", t));
+        let commands = task
+            .commands
+            .iter()
+            .flatten()
+            .map(|c| format!("$ {}", c.run));
+        tags.chain(commands)
+            .find_map(|text| fuzzy::positions(search, &text).map(|p| (Some(text), p)))
+    }
+    /// cycles the tag under the cursor through shown only, hidden and unfiltered
+    fn toggle_tag(&mut self) {
+        let Some(tag) = self.tags.get(self.tag_cursor).cloned() else {
+            return;
+        };
+        if let Some(pos) = self.filter.tags.iter().position(|t| *t == tag) {
+            self.filter.tags.remove(pos);
+            self.filter.skip.push(tag);
+        } else if let Some(pos) = self.filter.skip.iter().position(|t| *t == tag) {
+            self.filter.skip.remove(pos);
+        } else {
+            self.filter.tags.push(tag);
+        }
+        self.refilter();
+    }
+    /// Keeps the selection in the tasks left visible by a filter change.
+    fn refilter(&mut self) {
+        let len = self.visible().len();
+        self.tasklist.select(Some(
+            self.tasklist
+                .selected()
+                .unwrap_or(0)
+                .min(len.saturating_sub(1)),
+        ));
+        self.moved();
+    }
     /// runs the application's main loop until the user quits
     pub fn run(&mut self) -> io::Result<()> {
-        execute!(stdout(), EnterAlternateScreen)?;
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
         enable_raw_mode()?;
         let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
         terminal.clear()?;
         while !self.exit {
             terminal.draw(|frame| self.render_frame(frame))?;
-            self.handle_events()?;
+            match self.queue.pop_front() {
+                Some((index, job)) => self.run_job(index, job),
+                None => self.handle_events()?,
+            }
         }
-        execute!(stdout(), LeaveAlternateScreen)?;
+        execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
         disable_raw_mode()?;
         Ok(())
     }
@@ -52,18 +228,98 @@ impl Tui {
                 Constraint::Percentage(80),
             ])
             .split(frame.size());
+        //render the tag filter bar
+        let width = layout[0].width.max(1) as usize;
+        let tags_len: usize = self.tags.iter().map(|t| t.len() + 2).sum();
+        let task_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1 + tags_len.div_ceil(width).max(1) as u16),
+                Constraint::Min(0),
+                Constraint::Length(self.search.is_some() as u16),
+            ])
+            .split(layout[0]);
+        let tags = self.tags.iter().enumerate().map(|(i, tag)| {
+            let span = if self.filter.tags.contains(tag) {
+                format!("+{} ", tag).green()
+            } else if self.filter.skip.contains(tag) {
+                format!("-{} ", tag).red()
+            } else {
+                format!(" {} ", tag).into()
+            };
+            if i == self.tag_cursor {
+                span.reversed()
+            } else {
+                span
+            }
+        });
+        let tagbar = Paragraph::new(Line::from(tags.collect::<Vec<_>>()))
+            .wrap(Wrap { trim: false })
+            .block(Block::default().title(Title::from("Tags".blue()).alignment(Alignment::Center)));
+        frame.render_widget(tagbar, task_layout[0]);
         //render the list
-        let list = List::new(
-            self.server
-                .cfg
-                .tasks
-                .iter()
-                .map(|t| ListItem::new(t.name.clone())),
-        )
-        .block(Block::default().title(Title::from("Tasks".blue()).alignment(Alignment::Center)))
+        let mut title = match &self.server.cfg.profile {
+            Some(profile) => format!("Tasks ({})", profile),
+            None => "Tasks".to_string(),
+        };
+        if let Some((current, total)) = self.progress {
+            title += &format!(" task {}/{}", current, total);
+        }
+        let list = List::new(self.visible().into_iter().map(|i| {
+            let status = match self.server.status(i) {
+                TaskStatus::Pending => "".into(),
+                TaskStatus::Applied => " applied".green(),
+                TaskStatus::Stale => " stale".yellow(),
+                TaskStatus::Failed => " failed".red(),
+            };
+            let (glyph, duration) = match self.outputs[i].run {
+                Run::Idle => ("·".dark_gray(), "".into()),
+                Run::Queued => ("◦".yellow(), "".into()),
+                Run::Running => ("…".yellow(), "".into()),
+                Run::Done { success, duration } => (
+                    match success {
+                        true => "✓".green(),
+                        false => "✗".red(),
+                    },
+                    format!(" {:.1}s", duration.as_secs_f64()).dark_gray(),
+                ),
+            };
+            let task = &self.server.cfg.tasks[i];
+            let mark = match self.marked.contains(&i) {
+                true => "*".bold(),
+                false => " ".into(),
+            };
+            let mut spans = vec![mark, glyph, " ".into()];
+            match self.found(task) {
+                Some((None, positions)) => {
+                    spans.extend(fuzzy::highlight(&task.name, &positions, Style::default()))
+                }
+                Some((Some(text), positions)) => {
+                    spans.push(task.name.clone().into());
+                    spans.push(" ".into());
+                    spans.extend(fuzzy::highlight(
+                        &text,
+                        &positions,
+                        Style::new().dark_gray(),
+                    ));
+                }
+                None => spans.push(task.name.clone().into()),
+            }
+            spans.extend([status, duration]);
+            ListItem::new(Line::from(spans))
+        }))
+        .block(Block::default().title(Title::from(title.blue()).alignment(Alignment::Center)))
         .highlight_style(Style::default().bg(Color::LightBlue).fg(Color::Black))
         .highlight_symbol(">>");
-        frame.render_stateful_widget(list, layout[0], &mut self.tasklist);
+        frame.render_stateful_widget(list, task_layout[1], &mut self.tasklist);
+        //render the search below the list
+        if let Some(search) = &self.search {
+            let mut line = vec!["/".blue(), search.clone().into()];
+            if self.typing {
+                line.push("_".slow_blink());
+            }
+            frame.render_widget(Paragraph::new(Line::from(line)), task_layout[2]);
+        }
         //render the split line
         let split_line = Block::default().borders(Borders::LEFT);
         //render the logs
@@ -72,36 +328,199 @@ impl Tui {
             .direction(Direction::Vertical)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
             .split(layout[2]);
-        //render the output log
-        let outlog = List::new(
-            self.outlog
+        self.outscroll
+            .set_height(log_layout[0].height.saturating_sub(1) as usize);
+        self.errscroll
+            .set_height(log_layout[1].height.saturating_sub(1) as usize);
+        //render the output log of the selected task
+        let empty = Output::default();
+        let output = self.output().unwrap_or(&empty);
+        let start = self.outscroll.start(output.out.len());
+        let outlog = List::new(output.out[start..].iter().map(|o| ListItem::new(o.clone())))
+            .block(self.log_block("Output".green(), Focus::Output, self.outscroll));
+        frame.render_widget(outlog, log_layout[0]);
+        //render the error log, or the debug log in its place
+        let (lines, title): (Vec<_>, _) = match self.debug {
+            Some(level) => {
+                let lines = self.logs.lines(level);
+                let lines = lines[self.errscroll.start(lines.len())..]
+                    .iter()
+                    .map(|(level, line)| {
+                        let item = ListItem::new(format!("{:>5} {}", level, line));
+                        match *level {
+                            Level::ERROR => item.red(),
+                            Level::WARN => item.yellow(),
+                            Level::DEBUG => item.dark_gray(),
+                            _ => item,
+                        }
+                    })
+                    .collect();
+                (lines, format!("Debug log ({})", level).blue())
+            }
+            None => (
+                output.err[self.errscroll.start(output.err.len())..]
+                    .iter()
+                    .map(|e| ListItem::new(e.clone()))
+                    .collect(),
+                "Error".red(),
+            ),
+        };
+        let errlog = List::new(lines).block(self.log_block(title, Focus::Error, self.errscroll));
+        frame.render_widget(errlog, log_layout[1]);
+        self.outarea = log_layout[0];
+        self.errarea = log_layout[1];
+        //render the detail of the selected task over the logs
+        let applied = self
+            .detail
+            .map(|i| self.server.status(i) == TaskStatus::Applied);
+        if let Some((index, drifts)) = self.detail.and_then(|i| Some((i, self.drifts.get(&i)?))) {
+            let title = format!("{} (i to close)", self.server.cfg.tasks[index].name);
+            let lines = self.detail_lines(index, drifts, applied == Some(true));
+            let detail = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Title::from(title.blue()).alignment(Alignment::Center)),
+            );
+            frame.render_widget(ratatui::widgets::Clear, layout[2]);
+            frame.render_widget(detail, layout[2]);
+        }
+        //render the confirmation over the logs
+        if let Some(confirm) = &self.confirm {
+            let mut lines = confirm.summary.clone();
+            lines.push(Line::default());
+            lines.push(Line::from(vec![
+                "y".bold(),
+                " proceed  ".into(),
+                "d".bold(),
+                " dry run  ".into(),
+                "n".bold(),
+                " cancel".into(),
+            ]));
+            let title = match confirm.job {
+                Job::Exec => "Run?",
+                Job::Undo => "Undo?",
+            };
+            let popup = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Title::from(title.blue()).alignment(Alignment::Center)),
+            );
+            frame.render_widget(ratatui::widgets::Clear, layout[2]);
+            frame.render_widget(popup, layout[2]);
+        }
+        //render the past runs over the logs
+        if let Some((runs, state)) = &mut self.runs {
+            let names = runs
                 .iter()
-                .skip(
-                    self.outlog
-                        .len()
-                        .saturating_sub((log_layout[0].height - 1) as usize),
+                .map(|r| ListItem::new(r.file_name().unwrap_or_default().to_string_lossy()));
+            let list = List::new(names)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(Title::from("Past runs".blue()).alignment(Alignment::Center)),
                 )
-                .map(|o| ListItem::new(o.clone())),
-        )
-        .block(Block::default().title(Title::from("Output".green()).alignment(Alignment::Center)));
-        frame.render_stateful_widget(outlog, log_layout[0], &mut self.outputlist);
-        //render the error log
-        let errlog = List::new(
-            self.errlog
+                .highlight_style(Style::default().bg(Color::LightBlue).fg(Color::Black));
+            frame.render_widget(ratatui::widgets::Clear, layout[2]);
+            frame.render_stateful_widget(list, layout[2], state);
+        }
+    }
+
+    /// What a task does, each entry marked satisfied when it does not drift,
+    /// and its commands when it is `applied`.
+    fn detail_lines(&self, index: usize, drifts: &[Drift], applied: bool) -> Vec<Line<'static>> {
+        let task = &self.server.cfg.tasks[index];
+        let drifted = |item: &str| drifts.iter().any(|d| d.item == item);
+        let marker = |satisfied: bool| match satisfied {
+            true => "✓ ".green(),
+            false => "• ".yellow(),
+        };
+        let mut lines = Vec::new();
+        for drift in drifts.iter().filter(|d| d.kind == DriftKind::Error) {
+            lines.push(Line::from(drift.message.clone().red()));
+        }
+        let path = task.path.clone().unwrap_or_else(|| ".".to_string());
+        lines.push(Line::from(format!("path: {}", path)));
+        if let Some(depends) = &task.depends {
+            lines.push(Line::from(format!("depends: {}", depends.join(", "))));
+        }
+        let distri = self.server.distri();
+        let pkgs: Vec<_> = task
+            .pkg
+            .iter()
+            .flatten()
+            .filter(|p| p.distri == distri)
+            .flat_map(|p| p.install.iter().flatten())
+            .collect();
+        let mut section = |title: String, entries: Vec<(bool, String)>| {
+            if entries.is_empty() {
+                return;
+            }
+            lines.push(Line::from(title.bold()));
+            for (satisfied, entry) in entries {
+                lines.push(Line::from(vec![marker(satisfied), entry.into()]));
+            }
+        };
+        section(
+            format!("Packages ({})", distri),
+            pkgs.iter().map(|p| (!drifted(p), p.to_string())).collect(),
+        );
+        section(
+            "Links".to_string(),
+            task.slink
                 .iter()
-                .skip(
-                    self.errlog
-                        .len()
-                        .saturating_sub((log_layout[1].height - 1) as usize),
-                )
-                .map(|e| ListItem::new(e.clone())),
-        )
-        .block(Block::default().title(Title::from("Error".red()).alignment(Alignment::Center)));
-        frame.render_stateful_widget(errlog, log_layout[1], &mut self.errorlist);
+                .flatten()
+                .map(|l| {
+                    (
+                        !drifted(&l.dst),
+                        format!("[{}] {} -> {}", l.mode, l.src, l.dst),
+                    )
+                })
+                .collect(),
+        );
+        section(
+            "Templates".to_string(),
+            task.templates
+                .iter()
+                .flatten()
+                .map(|t| (!drifted(&t.dst), format!("{} -> {}", t.src, t.dst)))
+                .collect(),
+        );
+        section(
+            "Stow".to_string(),
+            task.stow
+                .iter()
+                .flatten()
+                .map(|s| (!drifted(&s.dst), format!("{} -> {}", s.src, s.dst)))
+                .collect(),
+        );
+        // commands cannot be checked, they are satisfied by the last run
+        section(
+            "Commands".to_string(),
+            task.commands
+                .iter()
+                .flatten()
+                .map(|c| (applied, c.run.clone()))
+                .collect(),
+        );
+        lines
+    }
+
+    /// The title of a log pane, underlined when focused.
+    fn log_block<'a>(&self, title: Span<'a>, pane: Focus, scroll: Scroll) -> Block<'a> {
+        let title = match self.focus == pane {
+            true => title.bold().underlined(),
+            false => title,
+        };
+        let mut titles = vec![title];
+        if scroll.paused {
+            titles.push(" (paused)".dark_gray());
+        }
+        Block::default().title(Title::from(Line::from(titles)).alignment(Alignment::Center))
     }
 
     fn handle_events(&mut self) -> io::Result<()> {
         match event::read()? {
+            Event::Mouse(mouse) if self.runs.is_none() => self.handle_mouse_event(mouse),
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
@@ -112,51 +531,427 @@ impl Tui {
         Ok(())
     }
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.runs.is_some() {
+            return self.handle_runs_key(key_event);
+        }
+        if let Some(confirm) = &self.confirm {
+            let dry_run = match key_event.code {
+                KeyCode::Char('y') | KeyCode::Enter => false,
+                KeyCode::Char('d') => true,
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.confirm = None;
+                    return;
+                }
+                _ => return,
+            };
+            let (job, indexes) = (confirm.job, confirm.indexes.clone());
+            self.confirm = None;
+            self.dry_run = dry_run;
+            return self.start(job, indexes);
+        }
+        if let (true, Some(search)) = (self.typing, &mut self.search) {
+            match key_event.code {
+                KeyCode::Char(c) => {
+                    search.push(c);
+                    return self.refilter();
+                }
+                KeyCode::Backspace => {
+                    search.pop();
+                    return self.refilter();
+                }
+                KeyCode::Esc => {
+                    self.search = None;
+                    self.typing = false;
+                    return self.refilter();
+                }
+                KeyCode::Enter => {
+                    self.typing = false;
+                    let order = self.server.cfg.order(&self.visible());
+                    return self.ask(Job::Exec, order);
+                }
+                _ => {}
+            }
+        }
         match key_event.code {
             KeyCode::Char('q') => self.exit(),
-            KeyCode::Up => {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Tasks => Focus::Output,
+                    Focus::Output => Focus::Error,
+                    Focus::Error => Focus::Tasks,
+                }
+            }
+            KeyCode::Up if self.focus == Focus::Tasks => {
                 if let Some(index) = self.tasklist.selected_mut().as_mut() {
                     if *index > 0 {
                         *index -= 1;
+                        self.moved();
                     }
                 }
             }
-            KeyCode::Down => {
+            KeyCode::Down if self.focus == Focus::Tasks => {
+                let len = self.visible().len();
                 if let Some(index) = self.tasklist.selected_mut().as_mut() {
-                    if *index < self.server.cfg.tasks.len() - 1 {
+                    if *index + 1 < len {
                         *index += 1;
+                        self.moved();
                     }
                 }
             }
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                let focus = self.focus;
+                if let Some((scroll, len)) = self.pane(focus) {
+                    let delta = match key_event.code {
+                        KeyCode::Up => -1,
+                        KeyCode::Down => 1,
+                        KeyCode::PageUp => -scroll.page(),
+                        _ => scroll.page(),
+                    };
+                    scroll.by(len, delta);
+                }
+            }
+            KeyCode::Home | KeyCode::End | KeyCode::Char('f') => {
+                let focus = self.focus;
+                if let Some((scroll, len)) = self.pane(focus) {
+                    match key_event.code {
+                        KeyCode::Home => scroll.home(),
+                        KeyCode::End => scroll.end(),
+                        _ => scroll.toggle_follow(len),
+                    }
+                }
+            }
+            KeyCode::Left => self.tag_cursor = self.tag_cursor.saturating_sub(1),
+            KeyCode::Right if self.tag_cursor + 1 < self.tags.len() => self.tag_cursor += 1,
+            KeyCode::Char('t') => self.toggle_tag(),
             KeyCode::Enter => {
-                if let Some(index) = self.tasklist.selected().as_ref() {
-                    let (outputs, _) = self.server.exec_by_index(*index);
-                    let (out, err): (Vec<_>, Vec<_>) = outputs
-                        .iter()
-                        .map(|o| {
-                            (
-                                o.stdout.split(|&b| b == b'\n').collect::<Vec<_>>(),
-                                o.stderr.split(|&b| b == b'\n').collect::<Vec<_>>(),
-                            )
-                        })
-                        .unzip();
-                    self.outlog.extend(
-                        out.concat()
-                            .into_iter()
-                            .map(|s| String::from_utf8_lossy(s).to_string()),
-                    );
-                    self.errlog.extend(
-                        err.into_iter()
-                            .filter(|e| !e.is_empty())
-                            .flatten()
-                            .map(|s| String::from_utf8_lossy(s).to_string()), // flatten the iterator of iterators
-                    );
+                let selected: Vec<_> = self.selected().into_iter().collect();
+                let order = self.server.cfg.order(&selected);
+                self.ask(Job::Exec, order)
+            }
+            KeyCode::Char(' ') => {
+                if let Some(index) = self.selected() {
+                    if !self.marked.remove(&index) {
+                        self.marked.insert(index);
+                    }
+                }
+            }
+            KeyCode::Char('a') => {
+                let visible = self.visible();
+                match visible.iter().all(|i| self.marked.contains(i)) {
+                    true => self.marked.clear(),
+                    false => self.marked.extend(visible),
                 }
             }
+            KeyCode::Char('r') => {
+                let marked: Vec<_> = self.marked.iter().copied().collect();
+                let order = self.server.cfg.order(&marked);
+                self.ask(Job::Exec, order)
+            }
+            KeyCode::Char('R') => {
+                let visible = self.visible();
+                let outdated = self.server.outdated();
+                let outdated: Vec<_> = outdated
+                    .into_iter()
+                    .filter(|i| visible.contains(i))
+                    .collect();
+                let order = self.server.cfg.order(&outdated);
+                self.ask(Job::Exec, order)
+            }
+            KeyCode::Char('l') => self.open_runs(),
+            KeyCode::Char('i') => match self.detail {
+                Some(_) => self.detail = None,
+                None => self.show_detail(),
+            },
+            KeyCode::Char('/') => {
+                self.search.get_or_insert_with(String::new);
+                self.typing = true;
+                self.focus = Focus::Tasks;
+            }
+            KeyCode::Esc => {
+                self.detail = None;
+                if self.search.take().is_some() {
+                    self.refilter();
+                }
+            }
+            KeyCode::Char('d') => {
+                self.debug = match self.debug {
+                    Some(_) => None,
+                    None => Some(Level::INFO),
+                }
+            }
+            KeyCode::Char('v') => {
+                if let Some(level) = &mut self.debug {
+                    let next = LEVELS.iter().position(|l| l == level).unwrap_or(0) + 1;
+                    *level = LEVELS[next % LEVELS.len()];
+                }
+            }
+            KeyCode::Char('u') => {
+                let selected = self.selected().into_iter().collect();
+                self.ask(Job::Undo, selected)
+            }
             _ => {}
         }
     }
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        let delta = match mouse.kind {
+            MouseEventKind::ScrollUp => -3,
+            MouseEventKind::ScrollDown => 3,
+            _ => return,
+        };
+        let position = Rect::new(mouse.column, mouse.row, 1, 1);
+        let pane = match () {
+            _ if self.outarea.intersects(position) => Focus::Output,
+            _ if self.errarea.intersects(position) => Focus::Error,
+            _ => return,
+        };
+        if let Some((scroll, len)) = self.pane(pane) {
+            scroll.by(len, delta);
+        }
+    }
+    /// The scroll position of a log pane and the number of lines it holds.
+    fn pane(&mut self, pane: Focus) -> Option<(&mut Scroll, usize)> {
+        match pane {
+            Focus::Tasks => None,
+            Focus::Output => {
+                let len = self.output().map_or(0, |o| o.out.len());
+                Some((&mut self.outscroll, len))
+            }
+            Focus::Error => {
+                let len = match self.debug {
+                    Some(level) => self.logs.lines(level).len(),
+                    None => self.output().map_or(0, |o| o.err.len()),
+                };
+                Some((&mut self.errscroll, len))
+            }
+        }
+    }
+    fn handle_runs_key(&mut self, key_event: KeyEvent) {
+        let Some((runs, state)) = &mut self.runs else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('l') => self.runs = None,
+            KeyCode::Up => state.select(Some(state.selected().unwrap_or(0).saturating_sub(1))),
+            KeyCode::Down => state.select(Some(
+                (state.selected().unwrap_or(0) + 1).min(runs.len().saturating_sub(1)),
+            )),
+            KeyCode::Enter => {
+                let run = state.selected().and_then(|i| runs.get(i)).cloned();
+                self.runs = None;
+                if let Some(run) = run {
+                    self.load_run(&run);
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Compares the selected task with the machine to show its detail.
+    fn show_detail(&mut self) {
+        self.detail = self.selected();
+        if let Some(index) = self.detail {
+            self.drift(index);
+        }
+    }
+    /// How the task at `index` differs from the machine, compared the first
+    /// time it is needed since the task last ran.
+    fn drift(&mut self, index: usize) -> &[Drift] {
+        let server = &mut self.server;
+        self.drifts
+            .entry(index)
+            .or_insert_with(|| server.drift_by_index(index))
+    }
+    /// Follows a change of the selected task.
+    fn moved(&mut self) {
+        self.past = None;
+        if self.detail.is_some() {
+            self.show_detail();
+        }
+    }
+    /// Lists the past run logs to pick one.
+    fn open_runs(&mut self) {
+        let runs = RunLog::default_dir()
+            .and_then(|dir| RunLog::list(&dir).ok())
+            .unwrap_or_default();
+        if runs.is_empty() {
+            self.past = Some(Output {
+                err: vec!["no past runs".to_string()],
+                ..Default::default()
+            });
+            return;
+        }
+        self.runs = Some((runs, ListState::default().with_selected(Some(0))));
+    }
+    /// Replaces the logs with the ones of a past run.
+    fn load_run(&mut self, run: &Path) {
+        match std::fs::read_to_string(run) {
+            Ok(text) => {
+                let name = run.file_name().unwrap_or_default().to_string_lossy();
+                let mut out = vec![format!("=== {} ===", name)];
+                out.extend(text.lines().map(str::to_string));
+                self.past = Some(Output {
+                    out,
+                    ..Default::default()
+                });
+            }
+            Err(e) => {
+                self.past = Some(Output {
+                    err: vec![format!("{}: {}", run.display(), e)],
+                    ..Default::default()
+                })
+            }
+        }
+    }
+    /// The index in the config of the selected task.
+    fn selected(&self) -> Option<usize> {
+        let visible = self.visible();
+        self.tasklist
+            .selected()
+            .and_then(|i| visible.get(i).copied())
+    }
+    /// The logs shown: a past run, or the last run of the selected task.
+    fn output(&self) -> Option<&Output> {
+        self.past
+            .as_ref()
+            .or_else(|| self.selected().map(|i| &self.outputs[i]))
+    }
+    /// Asks to confirm `job` for the tasks at `indexes`, summarising the
+    /// changes it plans.
+    fn ask(&mut self, job: Job, indexes: Vec<usize>) {
+        if indexes.is_empty() {
+            return;
+        }
+        let mut summary = Vec::new();
+        for &index in &indexes {
+            let task = &self.server.cfg.tasks[index];
+            summary.push(Line::from(task.name.clone().bold()));
+            let changes = match job {
+                Job::Exec => self.planned(index),
+                Job::Undo => self.reverted(index),
+            };
+            match changes.is_empty() {
+                true => summary.push(Line::from("  nothing to change".dark_gray())),
+                false => summary.extend(changes),
+            }
+        }
+        self.confirm = Some(Confirm {
+            job,
+            indexes,
+            summary,
+        });
+    }
+    /// The changes running the task at `index` would make.
+    fn planned(&mut self, index: usize) -> Vec<Line<'static>> {
+        self.drift(index);
+        let drifts = &self.drifts[&index];
+        self.server.plan(index, drifts).iter().map(change).collect()
+    }
+    /// The changes undoing the task at `index` would make.
+    fn reverted(&self, index: usize) -> Vec<Line<'static>> {
+        match self.server.undo_plan(index) {
+            Ok(changes) => changes.iter().map(change).collect(),
+            Err(e) => vec![Line::from(format!("  {}", e).red())],
+        }
+    }
+    /// Queues `job` for the tasks at `indexes`, in that order.
+    fn start(&mut self, job: Job, indexes: Vec<usize>) {
+        if indexes.is_empty() {
+            return;
+        }
+        for &index in &indexes {
+            self.outputs[index].run = Run::Queued;
+        }
+        self.past = None;
+        self.progress = Some((0, indexes.len()));
+        self.queue = indexes.into_iter().map(|index| (index, job)).collect();
+        self.next();
+    }
+    /// Marks the first job of the queue as running, or clears the progress
+    /// once the queue is empty.
+    fn next(&mut self) {
+        match (self.queue.front(), &mut self.progress) {
+            (Some((index, _)), Some((current, _))) => {
+                self.outputs[*index].run = Run::Running;
+                *current += 1;
+            }
+            (None, _) => self.progress = None,
+            _ => {}
+        }
+    }
+    /// Runs a job, replacing the logs of its task with the ones of the job.
+    /// The queued tasks depending on it are dropped when the job fails.
+    fn run_job(&mut self, index: usize, job: Job) {
+        let start = Instant::now();
+        let dry_run = self.server.dry_run();
+        self.server.set_dry_run(dry_run || self.dry_run);
+        let (steps, result) = job.run(&mut self.server, index);
+        self.server.set_dry_run(dry_run);
+        self.drifts.remove(&index);
+        if self.detail == Some(index) {
+            self.drift(index);
+        }
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        for step in &steps {
+            let (step_out, step_err) = step.lines();
+            if !step_err.is_empty() {
+                err.push(step.header());
+                err.extend(step_err);
+            }
+            out.extend(step_out);
+        }
+        self.outputs[index] = Output {
+            out,
+            err,
+            run: Run::Done {
+                success: result.is_ok(),
+                duration: start.elapsed(),
+            },
+        };
+        if result.is_err() {
+            self.drop_dependents(index);
+        }
+        self.next();
+    }
+    /// Drops the queued jobs of the tasks depending on the failed task at
+    /// `index`, directly or through another dropped task.
+    fn drop_dependents(&mut self, index: usize) {
+        let cfg = &self.server.cfg;
+        let mut failed = vec![cfg.tasks[index].name.clone()];
+        let mut kept = VecDeque::new();
+        for (queued, job) in self.queue.drain(..) {
+            if !cfg.depends_on(queued, &failed) {
+                kept.push_back((queued, job));
+                continue;
+            }
+            self.outputs[queued] = Output {
+                err: vec![format!(
+                    "skipped, a dependency failed: {}",
+                    failed.join(", ")
+                )],
+                ..Default::default()
+            };
+            failed.push(cfg.tasks[queued].name.clone());
+            if let Some((_, total)) = &mut self.progress {
+                *total -= 1;
+            }
+        }
+        self.queue = kept;
+    }
     fn exit(&mut self) {
         self.exit = true;
     }
 }
+
+/// A planned change, red when it overwrites or removes something.
+fn change(change: &Change) -> Line<'static> {
+    let line = format!("  {}", change);
+    Line::from(match change {
+        Change::Create(_) | Change::Install(_) | Change::Run(_) => line.green(),
+        Change::Replace(_) | Change::Backup(..) | Change::Keep(..) => line.yellow(),
+        Change::RunUninstall(_) => line.into(),
+        Change::Overwrite(_)
+        | Change::Conflict(_)
+        | Change::Remove(..)
+        | Change::Unstow(..)
+        | Change::Uninstall(_) => line.red(),
+    })
+}
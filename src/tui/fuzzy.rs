@@ -0,0 +1,43 @@
+use ratatui::prelude::*;
+
+/// The positions of the chars of `text` matching the ones of `pattern` in
+/// order, ignoring case, when they all match.
+pub fn positions(pattern: &str, text: &str) -> Option<Vec<usize>> {
+    let mut pattern = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut positions = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let Some(p) = pattern.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(*p)) {
+            positions.push(i);
+            pattern.next();
+        }
+    }
+    pattern.peek().is_none().then_some(positions)
+}
+
+/// `text` with the chars at `positions` highlighted.
+pub fn highlight(text: &str, positions: &[usize], style: Style) -> Vec<Span<'static>> {
+    text.chars()
+        .enumerate()
+        .map(|(i, c)| match positions.contains(&i) {
+            true => Span::styled(c.to_string(), style.yellow().bold()),
+            false => Span::styled(c.to_string(), style),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        assert_eq!(positions("", "zsh"), Some(vec![]));
+        assert_eq!(positions("nvm", "neovim"), Some(vec![0, 3, 5]));
+        assert_eq!(positions("NV", "neovim"), Some(vec![0, 3]));
+        assert_eq!(positions("vn", "neovim"), None);
+        assert_eq!(positions("zshrc", "zsh"), None);
+    }
+}
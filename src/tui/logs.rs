@@ -0,0 +1,90 @@
+//! Tracing events kept in memory for the debug view, as the terminal belongs
+//! to the interface.
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// How many events are kept, the oldest being dropped first
+pub const LINES_MAX: usize = 1000;
+
+/// A tracing layer recording the last events, shared with the interface
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<(Level, String)>>>,
+    max: usize,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(LINES_MAX)
+    }
+}
+
+impl LogBuffer {
+    /// A buffer keeping the last `max` events.
+    pub fn new(max: usize) -> Self {
+        Self {
+            lines: Arc::default(),
+            max,
+        }
+    }
+    /// The events at `level` or more severe.
+    pub fn lines(&self, level: Level) -> Vec<(Level, String)> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().filter(|(l, _)| *l <= level).cloned().collect()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut line = String::new();
+        event.record(&mut Message(&mut line));
+        let level = *event.metadata().level();
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == self.max {
+            lines.pop_front();
+        }
+        lines.push_back((level, line));
+    }
+}
+
+/// Formats the message of an event followed by its other fields.
+struct Message<'a>(&'a mut String);
+
+impl Visit for Message<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        let _ = match field.name() {
+            "message" => write!(self.0, "{:?}", value),
+            name => write!(self.0, "{}={:?}", name, value),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn levels() {
+        let buffer = LogBuffer::new(2);
+        let subscriber = tracing_subscriber::registry().with(buffer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("dropped");
+            tracing::debug!(task = "zsh", "linking");
+            tracing::warn!("no state file");
+        });
+        assert_eq!(buffer.lines(Level::TRACE).len(), 2);
+        assert_eq!(
+            buffer.lines(Level::WARN),
+            vec![(Level::WARN, "no state file".to_string())]
+        );
+        assert_eq!(buffer.lines(Level::DEBUG)[0].1, "linking task=\"zsh\"");
+    }
+}
@@ -0,0 +1,67 @@
+/// Position in a log pane, which follows the end of the log unless paused
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Scroll {
+    offset: usize,
+    /// Keep the view in place when lines are added
+    pub paused: bool,
+    /// Lines shown, as of the last render
+    height: usize,
+}
+
+impl Scroll {
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height;
+    }
+    pub fn page(&self) -> isize {
+        self.height.max(1) as isize
+    }
+    /// The first line shown of a log of `len` lines.
+    pub fn start(&self, len: usize) -> usize {
+        let last = len.saturating_sub(self.height);
+        match self.paused {
+            true => self.offset.min(last),
+            false => last,
+        }
+    }
+    /// Moves by `delta` lines, which pauses the pane.
+    pub fn by(&mut self, len: usize, delta: isize) {
+        let last = len.saturating_sub(self.height);
+        self.offset = self.start(len).saturating_add_signed(delta).min(last);
+        self.paused = true;
+    }
+    pub fn home(&mut self) {
+        self.offset = 0;
+        self.paused = true;
+    }
+    pub fn end(&mut self) {
+        self.paused = false;
+    }
+    pub fn toggle_follow(&mut self, len: usize) {
+        self.offset = self.start(len);
+        self.paused = !self.paused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow() {
+        let mut scroll = Scroll::default();
+        scroll.set_height(10);
+        assert_eq!(scroll.start(5), 0);
+        assert_eq!(scroll.start(25), 15);
+        scroll.by(25, -scroll.page());
+        assert_eq!(scroll.start(30), 5);
+        scroll.by(30, 100);
+        assert_eq!(scroll.start(30), 20);
+        assert!(scroll.paused);
+        scroll.home();
+        assert_eq!(scroll.start(30), 0);
+        scroll.end();
+        assert_eq!(scroll.start(40), 30);
+        scroll.toggle_follow(40);
+        assert_eq!(scroll.start(50), 30);
+    }
+}